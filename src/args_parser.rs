@@ -1,33 +1,389 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use hack_isc_vm_translator::{diagnostic::Diagnostic, output::Comments, Options};
+
+const USAGE: &str =
+    "usage: hack-isc-vm-translator [run | emulate | verify | test] <input>... [-o <output>] [--emit asm | hack] [-O] \
+                     [--shared-calls] [--shared-comparisons] [--size-report] [--lenient] \
+                     [--extended-constants] \
+                     [--stats table | json] [--map text | json] \
+                     [--comments none | command | verbose] \
+                     [--bootstrap | --no-bootstrap] [--steps <n>] [--ram <from>[..<to>]] \
+                     [--set <address>=<value>]";
+
+#[derive(PartialEq, Eq)]
+pub enum Mode {
+    Translate,
+    Run,
+    Emulate,
+    Verify,
+    Test,
+}
+
+#[derive(PartialEq, Eq)]
+pub enum Emit {
+    Asm,
+    Hack,
+}
+
+impl Emit {
+    fn extension(&self) -> &'static str {
+        match self {
+            Emit::Asm => "asm",
+            Emit::Hack => "hack",
+        }
+    }
+}
+
+// how a report is written
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
 
 pub struct Arguments {
+    pub mode: Mode,
     pub input_file_paths: Vec<PathBuf>,
     pub output_file_path: PathBuf,
+    pub emit: Emit,
+    pub bootstrap: bool,
+    pub optimize: bool,
+    pub shared_calls: bool,
+    pub shared_comparisons: bool,
+    pub size_report: bool,
+    pub lenient: bool,
+    pub extended_constants: bool,
+    pub stats: Option<Format>,
+    pub map: Option<Format>,
+    pub comments: Comments,
+    pub steps: u64,
+    pub ram_ranges: Vec<Range<usize>>,
+    pub ram_values: Vec<(usize, i16)>,
 }
 
 impl Arguments {
-    pub fn build(args: impl Iterator<Item = String>) -> Result<Self, &'static str> {
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Self, Diagnostic> {
+        let mut mode = Mode::Translate;
         let mut output_file_path = None;
+        let mut emit = Emit::Asm;
+        let mut bootstrap = None;
+        let mut optimize = false;
+        let mut shared_calls = false;
+        let mut shared_comparisons = false;
+        let mut size_report = false;
+        let mut lenient = false;
+        let mut extended_constants = false;
+        let mut stats = None;
+        let mut map = None;
+        let mut comments = Comments::Command;
+        let mut steps = None;
+        let mut ram_ranges = vec![];
+        let mut ram_values = vec![];
         let mut input_file_paths = vec![];
-        let mut iterator = args.into_iter().skip(1);
+        let mut input_directory = None;
+        let mut iterator = args.into_iter().skip(1).peekable();
+
+        match iterator.peek().map(String::as_str) {
+            Some("run") => mode = Mode::Run,
+            Some("emulate") => mode = Mode::Emulate,
+            Some("verify") => mode = Mode::Verify,
+            Some("test") => mode = Mode::Test,
+            _ => {}
+        }
+
+        if mode != Mode::Translate {
+            iterator.next();
+        }
 
         while let Some(arg) = iterator.next() {
             if arg == "-o" {
-                output_file_path = iterator.next()
+                output_file_path = Some(Self::value(&mut iterator, &arg)?)
+            } else if arg == "--emit" {
+                emit = match Self::value(&mut iterator, &arg)?.as_str() {
+                    "asm" => Emit::Asm,
+                    "hack" => Emit::Hack,
+                    value => {
+                        return Err(Diagnostic::new(format!("unknown output format `{value}`"))
+                            .with_hint("expected `--emit asm` or `--emit hack`"))
+                    }
+                }
+            } else if arg == "--bootstrap" {
+                bootstrap = Some(true)
+            } else if arg == "--no-bootstrap" {
+                bootstrap = Some(false)
+            } else if arg == "-O" {
+                optimize = true
+            } else if arg == "--shared-calls" {
+                shared_calls = true
+            } else if arg == "--shared-comparisons" {
+                shared_comparisons = true
+            } else if arg == "--size-report" {
+                size_report = true
+            } else if arg == "--lenient" {
+                lenient = true
+            } else if arg == "--extended-constants" {
+                extended_constants = true
+            } else if arg == "--stats" {
+                stats = Some(Self::format(
+                    &Self::value(&mut iterator, &arg)?,
+                    &arg,
+                    "table",
+                )?)
+            } else if arg == "--map" {
+                map = Some(Self::format(
+                    &Self::value(&mut iterator, &arg)?,
+                    &arg,
+                    "text",
+                )?)
+            } else if arg == "--comments" {
+                comments = match Self::value(&mut iterator, &arg)?.as_str() {
+                    "none" => Comments::None,
+                    "command" => Comments::Command,
+                    "verbose" => Comments::Verbose,
+                    value => {
+                        return Err(Diagnostic::new(format!("unknown comments `{value}`"))
+                            .with_hint("expected `--comments none`, `command` or `verbose`"))
+                    }
+                }
+            } else if arg == "--steps" {
+                steps = Some(Self::number(&Self::value(&mut iterator, &arg)?)?)
+            } else if arg == "--ram" {
+                ram_ranges.push(Self::range(&Self::value(&mut iterator, &arg)?)?)
+            } else if arg == "--set" {
+                let value = Self::value(&mut iterator, &arg)?;
+                let (address, value) = value.split_once('=').ok_or_else(|| {
+                    Diagnostic::new(format!("invalid RAM assignment `{value}`"))
+                        .with_hint("expected `--set <address>=<value>`, e.g. `--set 0=256`")
+                })?;
+
+                ram_values.push((Self::number(address)?, Self::number(value)?))
+            } else if arg.starts_with('-') {
+                return Err(Diagnostic::new(format!("unknown option `{arg}`")).with_hint(USAGE));
             } else {
-                input_file_paths.push(Path::new(&arg).to_owned())
+                let path = Path::new(&arg);
+
+                // test scripts are looked up when running them
+                if mode == Mode::Test {
+                    input_file_paths.push(path.to_owned())
+                } else if path.is_dir() {
+                    input_file_paths.extend(Self::expand_directory(path)?);
+                    input_directory = Some(path.to_owned());
+                } else {
+                    input_file_paths.push(path.to_owned())
+                }
             }
         }
 
-        if input_file_paths.len() <= 0 {
-            return Err("Required a input file");
+        if input_file_paths.is_empty() {
+            return Err(Diagnostic::new("no input files").with_hint(USAGE));
         }
 
-        let output_file_path = output_file_path.expect("Output file path");
+        let output_file_path = match output_file_path {
+            Some(path) => Path::new(&path).to_owned(),
+            None if mode == Mode::Test => PathBuf::new(),
+            None => Self::default_output_file_path(
+                &input_file_paths,
+                input_directory.as_deref(),
+                emit.extension(),
+            )?,
+        };
+
+        let bootstrap = bootstrap.unwrap_or_else(|| {
+            input_file_paths
+                .iter()
+                .any(|path| path.file_name().is_some_and(|name| name == "Sys.vm"))
+        });
+
+        // a VM instruction takes a few dozen CPU cycles
+        let steps = steps.unwrap_or(match mode {
+            Mode::Emulate => 50_000_000,
+            _ => 1_000_000,
+        });
 
         Ok(Arguments {
+            mode,
             input_file_paths,
-            output_file_path: Path::new(&output_file_path).to_owned(),
+            output_file_path,
+            emit,
+            bootstrap,
+            optimize,
+            shared_calls,
+            shared_comparisons,
+            size_report,
+            lenient,
+            extended_constants,
+            stats,
+            map,
+            comments,
+            steps,
+            ram_ranges,
+            ram_values,
         })
     }
+
+    pub fn options(&self) -> Options {
+        Options {
+            bootstrap: self.bootstrap,
+            optimize: self.optimize,
+            shared_calls: self.shared_calls,
+            shared_comparisons: self.shared_comparisons,
+            lenient: self.lenient,
+            extended_constants: self.extended_constants,
+        }
+    }
+
+    fn value(
+        iterator: &mut impl Iterator<Item = String>,
+        flag: &str,
+    ) -> Result<String, Diagnostic> {
+        iterator.next().ok_or_else(|| {
+            Diagnostic::new(format!("missing value after `{flag}`")).with_hint(USAGE)
+        })
+    }
+
+    // `text` names the plain text format of the option
+    fn format(value: &str, flag: &str, text: &str) -> Result<Format, Diagnostic> {
+        match value {
+            "json" => Ok(Format::Json),
+            value if value == text => Ok(Format::Text),
+            _ => Err(Diagnostic::new(format!("unknown format `{value}`"))
+                .with_hint(format!("expected `{flag} {text}` or `{flag} json`"))),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(value: &str) -> Result<T, Diagnostic> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| Diagnostic::new(format!("invalid number `{value}`")).with_hint(USAGE))
+    }
+
+    // `from..to` (exclusive) or a single address
+    fn range(value: &str) -> Result<Range<usize>, Diagnostic> {
+        match value.split_once("..") {
+            Some((from, to)) => Ok(Self::number(from)?..Self::number(to)?),
+            None => {
+                let address = Self::number(value)?;
+
+                Ok(address..address + 1)
+            }
+        }
+    }
+
+    pub fn expand_directory(directory: &Path) -> Result<Vec<PathBuf>, Diagnostic> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(directory)
+            .map_err(|e| {
+                Diagnostic::new(format!(
+                    "could not read input directory `{}`: {e}",
+                    directory.display()
+                ))
+            })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "vm"))
+            .collect();
+
+        // Sys.vm goes first, the rest in name order
+        paths.sort_by_key(|path| {
+            (
+                path.file_name().is_none_or(|name| name != "Sys.vm"),
+                path.clone(),
+            )
+        });
+
+        Ok(paths)
+    }
+
+    fn default_output_file_path(
+        input_file_paths: &[PathBuf],
+        input_directory: Option<&Path>,
+        extension: &str,
+    ) -> Result<PathBuf, Diagnostic> {
+        if let Some(directory) = input_directory {
+            let directory = fs::canonicalize(directory).map_err(|e| {
+                Diagnostic::new(format!(
+                    "could not read input directory `{}`: {e}",
+                    directory.display()
+                ))
+            })?;
+            let name = directory.file_name().ok_or_else(|| {
+                Diagnostic::new("could not derive the output file name from the input directory")
+                    .with_hint("pass the output file explicitly with `-o <output>`")
+            })?;
+
+            return Ok(directory.join(format!("{0}.{extension}", name.to_string_lossy())));
+        }
+
+        Ok(input_file_paths[0].with_extension(extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(args: &[&str]) -> Arguments {
+        Arguments::build(
+            ["hack-isc-vm-translator"]
+                .iter()
+                .chain(args)
+                .map(|arg| arg.to_string()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn bootstraps_when_sys_vm_is_an_input() {
+        assert!(build(&["Main.vm", "dir/Sys.vm", "-o", "out.asm"]).bootstrap);
+        assert!(!build(&["Main.vm", "-o", "out.asm"]).bootstrap);
+        assert!(!build(&["Sys.vm", "--no-bootstrap", "-o", "out.asm"]).bootstrap);
+        assert!(build(&["Main.vm", "--bootstrap", "-o", "out.asm"]).bootstrap);
+    }
+
+    #[test]
+    fn expands_a_directory_with_sys_vm_first() {
+        let directory = std::env::temp_dir().join(format!("Expand{0}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        for name in ["Main.vm", "Sys.vm", "Array.vm", "notes.txt"] {
+            fs::write(directory.join(name), "").unwrap();
+        }
+
+        let arguments = Arguments::build(
+            ["hack-isc-vm-translator", directory.to_str().unwrap()]
+                .into_iter()
+                .map(str::to_string),
+        );
+        fs::remove_dir_all(&directory).unwrap();
+        let arguments = arguments.unwrap();
+
+        let names: Vec<_> = arguments
+            .input_file_paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Sys.vm", "Array.vm", "Main.vm"]);
+        assert!(arguments
+            .output_file_path
+            .ends_with(format!("Expand{0}/Expand{0}.asm", std::process::id())));
+        assert!(arguments.bootstrap);
+    }
+
+    #[test]
+    fn derives_the_output_from_a_single_file() {
+        let arguments = build(&["dir/Foo.vm", "--emit", "hack"]);
+
+        assert_eq!(arguments.output_file_path, Path::new("dir/Foo.hack"));
+        assert!(!arguments.bootstrap);
+    }
+
+    #[test]
+    fn usage_is_one_line_without_gaps() {
+        assert!(!USAGE.contains("  "));
+        assert!(!USAGE.contains('\n'));
+    }
 }
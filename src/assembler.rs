@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use crate::diagnostic::Diagnostic;
+
+pub const VARIABLE_BASE: u16 = 16;
+
+pub struct Program {
+    pub words: Vec<u16>,
+    pub labels: HashMap<String, u16>,
+    // in allocation order, starting at RAM[16]
+    pub variables: Vec<(String, u16)>,
+}
+
+impl Program {
+    // the `.hack` text format, one 16-bit binary word per line
+    pub fn hack(&self) -> String {
+        self.words
+            .iter()
+            .map(|word| format!("{word:016b}\n"))
+            .collect()
+    }
+}
+
+pub const PREDEFINED: [(&str, u16); 23] = [
+    ("SP", 0),
+    ("LCL", 1),
+    ("ARG", 2),
+    ("THIS", 3),
+    ("THAT", 4),
+    ("R0", 0),
+    ("R1", 1),
+    ("R2", 2),
+    ("R3", 3),
+    ("R4", 4),
+    ("R5", 5),
+    ("R6", 6),
+    ("R7", 7),
+    ("R8", 8),
+    ("R9", 9),
+    ("R10", 10),
+    ("R11", 11),
+    ("R12", 12),
+    ("R13", 13),
+    ("R14", 14),
+    ("R15", 15),
+    ("SCREEN", 16384),
+    ("KBD", 24576),
+];
+
+fn clean(line: &str) -> &str {
+    line.split("//").next().unwrap_or_default().trim()
+}
+
+// `name` only labels the diagnostics
+pub fn assemble<'a>(
+    name: &str,
+    lines: impl Iterator<Item = &'a str> + Clone,
+) -> Result<Program, Diagnostic> {
+    let mut labels = HashMap::new();
+    let mut address = 0u16;
+
+    for (n, raw) in lines.clone().enumerate() {
+        let line = clean(raw);
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match line.strip_prefix('(').and_then(|l| l.strip_suffix(')')) {
+            Some(label) => {
+                // the lines given may not be the lines of the written file, like generated code
+                // without its comments, the ROM addresses are the same either way
+                if let Some(first) = labels.insert(label.to_string(), address) {
+                    let column = raw.find(line).unwrap_or_default() + 1;
+
+                    return Err(Diagnostic::new(format!(
+                        "label `{label}` is defined more than once"
+                    ))
+                    .at(name, n + 1, column, line.chars().count(), raw)
+                    .with_note(format!(
+                        "first defined at ROM {first}, again at ROM {address}"
+                    )));
+                }
+            }
+            None => address += 1,
+        }
+    }
+
+    let predefined: HashMap<&str, u16> = PREDEFINED.into_iter().collect();
+    let mut variables: Vec<(String, u16)> = vec![];
+    let mut variable_addresses: HashMap<String, u16> = HashMap::new();
+    let mut words = vec![];
+
+    for (n, raw) in lines.enumerate() {
+        let line = clean(raw);
+
+        if line.is_empty() || line.starts_with('(') {
+            continue;
+        }
+
+        let error = |message: String| {
+            let column = raw.find(line).unwrap_or_default() + 1;
+
+            Diagnostic::new(message).at(name, n + 1, column, line.chars().count(), raw)
+        };
+
+        let word = if let Some(symbol) = line.strip_prefix('@') {
+            if symbol.chars().all(|c| c.is_ascii_digit()) {
+                symbol
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|&value| value <= i16::MAX as u16)
+                    .ok_or_else(|| error(format!("constant `{symbol}` is too large")))?
+            } else if !is_symbol(symbol) {
+                return Err(error(format!("invalid symbol `{symbol}`")));
+            } else if let Some(&value) = predefined.get(symbol).or(labels.get(symbol)) {
+                value
+            } else if let Some(&value) = variable_addresses.get(symbol) {
+                value
+            } else {
+                let value = VARIABLE_BASE + variables.len() as u16;
+
+                variables.push((symbol.to_string(), value));
+                variable_addresses.insert(symbol.to_string(), value);
+                value
+            }
+        } else {
+            encode_c_instruction(line)
+                .ok_or_else(|| error(format!("invalid instruction `{line}`")))?
+        };
+
+        words.push(word);
+    }
+
+    Ok(Program {
+        words,
+        labels,
+        variables,
+    })
+}
+
+// letters, digits, `_`, `.`, `$` and `:`, not starting with a digit
+fn is_symbol(symbol: &str) -> bool {
+    !symbol.starts_with(|c: char| c.is_ascii_digit())
+        && !symbol.is_empty()
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c))
+}
+
+fn encode_c_instruction(line: &str) -> Option<u16> {
+    let (dest, rest) = match line.split_once('=') {
+        Some((dest, rest)) => (dest.trim(), rest),
+        None => ("", line),
+    };
+    let (comp, jump) = match rest.split_once(';') {
+        Some((comp, jump)) => (comp.trim(), jump.trim()),
+        None => (rest.trim(), ""),
+    };
+
+    let mut dest_bits = 0;
+
+    for register in dest.chars() {
+        let bit = match register {
+            'A' => 0b100,
+            'D' => 0b010,
+            'M' => 0b001,
+            _ => return None,
+        };
+
+        if dest_bits & bit != 0 {
+            return None;
+        }
+
+        dest_bits |= bit;
+    }
+
+    let jump_bits = match jump {
+        "" => 0b000,
+        "JGT" => 0b001,
+        "JEQ" => 0b010,
+        "JGE" => 0b011,
+        "JLT" => 0b100,
+        "JNE" => 0b101,
+        "JLE" => 0b110,
+        "JMP" => 0b111,
+        _ => return None,
+    };
+
+    // the `a` bit selects M instead of A as the second operand
+    let (a_bit, comp) = if comp.contains('M') {
+        (1, comp.replace('M', "A"))
+    } else {
+        (0, comp.to_string())
+    };
+
+    let comp_bits = match comp.as_str() {
+        "0" => 0b101010,
+        "1" => 0b111111,
+        "-1" => 0b111010,
+        "D" => 0b001100,
+        "A" => 0b110000,
+        "!D" => 0b001101,
+        "!A" => 0b110001,
+        "-D" => 0b001111,
+        "-A" => 0b110011,
+        "D+1" | "1+D" => 0b011111,
+        "A+1" | "1+A" => 0b110111,
+        "D-1" => 0b001110,
+        "A-1" => 0b110010,
+        "D+A" | "A+D" => 0b000010,
+        "D-A" => 0b010011,
+        "A-D" => 0b000111,
+        "D&A" | "A&D" => 0b000000,
+        "D|A" | "A|D" => 0b010101,
+        _ => return None,
+    };
+
+    Some(0b111 << 13 | a_bit << 12 | comp_bits << 6 | dest_bits << 3 | jump_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(source: &str) -> Vec<String> {
+        assemble("test.asm", source.lines())
+            .unwrap()
+            .words
+            .iter()
+            .map(|word| format!("{word:016b}"))
+            .collect()
+    }
+
+    #[test]
+    fn encodes_c_instructions() {
+        assert_eq!(
+            words("D=M\n0;JMP\nAM=M-1\nD;JGT\nM=D+1\nD=!A"),
+            [
+                "1111110000010000",
+                "1110101010000111",
+                "1111110010101000",
+                "1110001100000001",
+                "1110011111001000",
+                "1110110001010000",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_c_instructions() {
+        for line in ["DD=M", "D=M+2", "0;JMPX", "X=1"] {
+            assert!(assemble("test.asm", [line].into_iter()).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn resolves_labels_and_predefined_symbols() {
+        assert_eq!(
+            words("(START)\n@LOOP\n0;JMP\n(LOOP)\n@START\n@SP\n@KBD"),
+            [
+                "0000000000000010",
+                "1110101010000111",
+                "0000000000000000",
+                "0000000000000000",
+                "0110000000000000",
+            ]
+        );
+    }
+
+    #[test]
+    fn allocates_variables_from_16() {
+        let program = assemble("test.asm", "@i\n@sum\n@i\n@R15".lines()).unwrap();
+
+        assert_eq!(program.words, [16, 17, 16, 15]);
+        assert_eq!(
+            program.variables,
+            [("i".to_string(), 16), ("sum".to_string(), 17)]
+        );
+    }
+
+    #[test]
+    fn checks_the_15_bit_constant_range() {
+        assert_eq!(words("@32767"), ["0111111111111111"]);
+        assert!(assemble("test.asm", ["@32768"].into_iter()).is_err());
+    }
+
+    #[test]
+    fn writes_one_word_per_line() {
+        let program = assemble("test.asm", "@5\nD=A".lines()).unwrap();
+
+        assert_eq!(program.hack(), "0000000000000101\n1110110000010000\n");
+    }
+
+    #[test]
+    fn rejects_duplicate_labels() {
+        let error = assemble("test.asm", "(A)\n@A\n(A)\n0;JMP".lines())
+            .err()
+            .unwrap();
+
+        assert_eq!(error.message, "label `A` is defined more than once");
+        assert_eq!(error.line, 3);
+        assert_eq!(error.notes, ["first defined at ROM 0, again at ROM 1"]);
+    }
+}
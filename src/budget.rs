@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    commands::memory_access::MemorySegment,
+    diagnostic::{Diagnostic, Diagnostics},
+    instruction::VmInstruction,
+    module::{functions, Module},
+    output::Output,
+};
+
+pub const ROM_SIZE: usize = 32768;
+// statics are allocated from RAM[16] up to the stack at RAM[256]
+pub const STATIC_LIMIT: usize = 240;
+// entries listed in a breakdown
+const LISTED: usize = 10;
+
+// fails when the program doesn't fit the ROM or its statics run into the stack
+pub fn check(modules: &[Module], output: &Output) -> Result<(), Diagnostics> {
+    let mut diagnostics = vec![];
+    let size = output.size();
+
+    if size > ROM_SIZE {
+        let mut diagnostic = Diagnostic::new(format!(
+            "the program needs {size} instructions, {0} more than the ROM holds ({ROM_SIZE})",
+            size - ROM_SIZE
+        ));
+
+        for (name, size) in top(function_sizes(modules, output), "function") {
+            diagnostic = diagnostic.with_note(format!("{size} instructions in {name}"));
+        }
+
+        for (name, size) in top(file_sizes(modules, output), "file") {
+            diagnostic = diagnostic.with_note(format!("{size} instructions in {name}"));
+        }
+
+        diagnostics.push(diagnostic.with_hint(
+            "`-O`, `--shared-calls` and `--shared-comparisons` make the program smaller",
+        ));
+    }
+
+    let statics = static_counts(modules);
+    let count: usize = statics.iter().map(|(_, count)| count).sum();
+
+    if count > STATIC_LIMIT {
+        let mut diagnostic = Diagnostic::new(format!(
+            "the program uses {count} static variables, {0} more than fit below the stack \
+             ({STATIC_LIMIT}, RAM[16] to RAM[255])",
+            count - STATIC_LIMIT
+        ));
+
+        let statics = statics.into_iter().map(|(name, count)| (Some(name), count));
+
+        for (name, count) in top(statics.collect(), "file") {
+            diagnostic = diagnostic.with_note(format!("{count} statics in {name}"));
+        }
+
+        diagnostics.push(diagnostic);
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(Diagnostics(diagnostics));
+    }
+
+    Ok(())
+}
+
+// the largest entries first and the rest summed up, none stands for the code outside
+// of the modules
+fn top(mut entries: Vec<(Option<String>, usize)>, kind: &str) -> Vec<(String, usize)> {
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let rest = entries.split_off(entries.len().min(LISTED));
+    let mut top: Vec<(String, usize)> = entries
+        .into_iter()
+        .map(|(name, size)| match name {
+            Some(name) => (format!("{kind} {name}"), size),
+            None => ("the bootstrap and shared routines".to_string(), size),
+        })
+        .collect();
+
+    if !rest.is_empty() {
+        top.push((
+            format!("{0} other {kind}s", rest.len()),
+            rest.iter().map(|(_, size)| size).sum(),
+        ));
+    }
+
+    top
+}
+
+// by function, code outside of a function by file as `File.vm (top level)` and code
+// outside of the modules as none
+pub fn function_sizes(modules: &[Module], output: &Output) -> Vec<(Option<String>, usize)> {
+    let functions = functions(modules);
+
+    sizes(output, |module, instruction| {
+        match functions[module][instruction] {
+            Some(function) => function.to_string(),
+            None => format!("{0} (top level)", modules[module].file_name),
+        }
+    })
+}
+
+pub fn file_sizes(modules: &[Module], output: &Output) -> Vec<(Option<String>, usize)> {
+    sizes(output, |module, _| modules[module].file_name.clone())
+}
+
+fn sizes(output: &Output, name: impl Fn(usize, usize) -> String) -> Vec<(Option<String>, usize)> {
+    let mut sizes: BTreeMap<Option<String>, usize> = BTreeMap::new();
+
+    for block in &output.blocks {
+        let name = block
+            .location
+            .map(|location| name(location.module, location.instruction));
+
+        *sizes.entry(name).or_default() += block.size();
+    }
+
+    sizes.into_iter().collect()
+}
+
+// the distinct static variables of every file
+pub fn static_counts(modules: &[Module]) -> Vec<(String, usize)> {
+    modules
+        .iter()
+        .map(|module| {
+            let indices: BTreeSet<u16> = module
+                .instructions
+                .iter()
+                .filter_map(|instruction| match instruction.kind {
+                    VmInstruction::Push(MemorySegment::Static, i)
+                    | VmInstruction::Pop(MemorySegment::Static, i) => Some(i),
+                    _ => None,
+                })
+                .collect();
+
+            (module.file_name.clone(), indices.len())
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, translate_modules, Options};
+
+    #[test]
+    fn counts_distinct_statics_per_file() {
+        let sources = [
+            ("A.vm", "push static 0\npop static 0\npush static 3\n"),
+            ("B.vm", "push constant 1\n"),
+        ];
+        let modules = parse(&sources, &Options::default()).unwrap();
+
+        assert_eq!(static_counts(&modules), [("A.vm".to_string(), 2)]);
+    }
+
+    #[test]
+    fn rejects_statics_running_into_the_stack() {
+        let source: String = (0..=STATIC_LIMIT)
+            .map(|i| format!("push static {i}\n"))
+            .collect();
+        let sources = [("Main.vm", source.as_str())];
+        let modules = parse(&sources, &Options::default()).unwrap();
+        let output = translate_modules(&modules, &Options::default());
+
+        let errors = check(&modules, &output).err().unwrap();
+
+        assert_eq!(
+            errors.0[0].message,
+            "the program uses 241 static variables, 1 more than fit below the stack \
+             (240, RAM[16] to RAM[255])"
+        );
+    }
+
+    #[test]
+    fn attributes_sizes_to_functions() {
+        let sources = [(
+            "Main.vm",
+            "push constant 1\nfunction Main.f 0\npush constant 2\nreturn\n",
+        )];
+        let modules = parse(&sources, &Options::default()).unwrap();
+        let output = translate_modules(&modules, &Options::default());
+        let sizes = function_sizes(&modules, &output);
+
+        let names: Vec<_> = sizes.iter().map(|(name, _)| name.as_deref()).collect();
+        assert_eq!(names, [Some("Main.f"), Some("Main.vm (top level)")]);
+        assert_eq!(
+            sizes.iter().map(|(_, size)| size).sum::<usize>(),
+            output.size()
+        );
+        assert!(check(&modules, &output).is_ok());
+    }
+}
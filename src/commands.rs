@@ -1,12 +1,16 @@
 pub mod arithmetic;
+pub mod bootstrap;
 pub mod branching;
 pub mod functional;
 pub mod memory_access;
+pub mod shared;
 
+use crate::{context::Context, instruction::VmInstruction};
 use arithmetic::ArithmeticCommand;
-use branching::BranchingCommand;
-use functional::FunctionalCommand;
-use memory_access::MemoryAccessCommand;
+use branching::{BranchingCommand, BranchingCommandType};
+use functional::{FunctionalCommand, FunctionalCommandType};
+use memory_access::{MemoryAccessCommand, MemoryCommandType};
+use shared::SharedRoutine;
 
 pub trait Command {
     fn generate(&self) -> Vec<String>;
@@ -15,36 +19,61 @@ pub trait Command {
 pub struct CommandType;
 
 impl CommandType {
-    pub fn new<'a>(
-        command: &'a str,
-        file_name: &'a str,
-        function_name: Option<&'a str>,
-        ret: u16,
-    ) -> Option<Box<dyn Command + 'a>> {
-        let assume_arithmetic: Option<ArithmeticCommand<'_>> = ArithmeticCommand::new(command);
+    pub fn build<'a>(
+        instruction: &'a VmInstruction,
+        context: &'a Context,
+    ) -> Box<dyn Command + 'a> {
+        let module_name = context.module_name.as_str();
+        // the enclosing function, synthetic at the top level of the module
+        let scope = context.scope();
+        let ret = context.ret();
 
-        if let Some(command) = assume_arithmetic {
-            return Some(Box::new(command));
+        match instruction {
+            VmInstruction::Arithmetic(command_type) => {
+                Box::new(ArithmeticCommand::new(*command_type, context))
+            }
+            VmInstruction::Push(segment, i) => Box::new(MemoryAccessCommand::new(
+                MemoryCommandType::Push,
+                *segment,
+                *i,
+                module_name,
+            )),
+            VmInstruction::Pop(segment, i) => Box::new(MemoryAccessCommand::new(
+                MemoryCommandType::Pop,
+                *segment,
+                *i,
+                module_name,
+            )),
+            VmInstruction::Label(label) => Box::new(BranchingCommand::new(
+                BranchingCommandType::Label,
+                label,
+                scope,
+            )),
+            VmInstruction::Goto(label) => Box::new(BranchingCommand::new(
+                BranchingCommandType::Goto,
+                label,
+                scope,
+            )),
+            VmInstruction::IfGoto(label) => Box::new(BranchingCommand::new(
+                BranchingCommandType::IfGoto,
+                label,
+                scope,
+            )),
+            VmInstruction::Function(name, n) => Box::new(FunctionalCommand::new(
+                FunctionalCommandType::Function,
+                Some(name),
+                *n,
+                scope,
+                ret,
+            )),
+            VmInstruction::Call(name, n) => Box::new(
+                FunctionalCommand::call(scope, name, *n, ret)
+                    .shared(context.shares(SharedRoutine::Call)),
+            ),
+            VmInstruction::Return => Box::new(
+                FunctionalCommand::new(FunctionalCommandType::Return, None, 0, scope, ret)
+                    .shared(context.shares(SharedRoutine::Return)),
+            ),
         }
-
-        let assume_memory_access = MemoryAccessCommand::new(command, file_name);
-
-        if let Some(command) = assume_memory_access {
-            return Some(Box::new(command));
-        }
-
-        let assume_branching = BranchingCommand::new(command, file_name, function_name);
-
-        if let Some(command) = assume_branching {
-            return Some(Box::new(command));
-        }
-
-        let assume_functional = FunctionalCommand::new(command, file_name, function_name, ret);
-
-        if let Some(command) = assume_functional {
-            return Some(Box::new(command));
-        }
-
-        None
     }
 }
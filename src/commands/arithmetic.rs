@@ -1,6 +1,5 @@
-use rand::{distributions::Alphanumeric, Rng};
-
-enum ArithmeticType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithmeticType {
     Add,
     Sub,
     Neg,
@@ -12,16 +11,14 @@ enum ArithmeticType {
     Not,
 }
 
-use crate::commands::Command;
+use crate::{
+    commands::{shared::SharedRoutine, Command},
+    context::Context,
+};
 use ArithmeticType::*;
 
-pub struct ArithmeticCommand<'a> {
-    command_type: ArithmeticType,
-    _command: &'a str,
-}
-
-impl<'a> ArithmeticCommand<'a> {
-    pub fn new(command: &'a str) -> Option<Self> {
+impl ArithmeticType {
+    pub fn parse(command: &str) -> Option<Self> {
         let command_type = match command {
             "add" => Add,
             "sub" => Sub,
@@ -35,14 +32,49 @@ impl<'a> ArithmeticCommand<'a> {
             _ => return None,
         };
 
-        return Some(Self {
+        Some(command_type)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Add => "add",
+            Sub => "sub",
+            Neg => "neg",
+            Eq => "eq",
+            Gt => "gt",
+            Lt => "lt",
+            And => "and",
+            Or => "or",
+            Not => "not",
+        }
+    }
+}
+
+pub struct ArithmeticCommand {
+    command_type: ArithmeticType,
+    label: Option<String>,
+    // the shared comparison routine to jump to instead of inlining the comparison
+    routine: Option<SharedRoutine>,
+}
+
+impl ArithmeticCommand {
+    pub fn new(command_type: ArithmeticType, context: &Context) -> Self {
+        let label = match command_type {
+            Eq | Gt | Lt => Some(context.comparison_label()),
+            _ => None,
+        };
+
+        let routine = SharedRoutine::comparison(command_type).filter(|r| context.shares(*r));
+
+        Self {
             command_type,
-            _command: command,
-        });
+            label,
+            routine,
+        }
     }
 }
 
-impl<'a> ArithmeticCommand<'a> {
+impl ArithmeticCommand {
     fn generate_2_operand_arithmetic_code(&self) -> Vec<String> {
         let main_isc = match self.command_type {
             Add => "D=D+M",
@@ -85,19 +117,8 @@ impl<'a> ArithmeticCommand<'a> {
             _ => unreachable!(),
         };
 
-        let label: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .filter(|c| c.is_ascii_alphabetic()) // Keep only alphabetic characters
-            .take(10) // Take 10 characters
-            .map(char::from)
-            .collect();
-
-        let end_label: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .filter(|c| c.is_ascii_alphabetic()) // Keep only alphabetic characters
-            .take(10) // Take 10 characters
-            .map(char::from)
-            .collect();
+        let label = self.label.as_deref().expect("a comparison label");
+        let end_label = format!("{label}.END");
 
         vec![
             "@SP".to_string(),
@@ -123,13 +144,31 @@ impl<'a> ArithmeticCommand<'a> {
     }
 }
 
-impl<'a> Command for ArithmeticCommand<'a> {
+impl ArithmeticCommand {
+    // the return address in D, the comparison label is where the routine returns to
+    fn generate_shared_comparison_code(&self, routine: SharedRoutine) -> Vec<String> {
+        let label = self.label.as_deref().expect("a comparison label");
+
+        vec![
+            format!("@{label}"),
+            "D=A".to_string(),
+            format!("@{0}", routine.label()),
+            "0;JMP".to_string(),
+            format!("({label})"),
+        ]
+    }
+}
+
+impl Command for ArithmeticCommand {
     fn generate(&self) -> Vec<String> {
         match self.command_type {
             Add | Sub | And | Or => self.generate_2_operand_arithmetic_code(),
             Neg => self.generate_neg_operand_arithmetic_code(),
             Not => self.generate_not_operand_arithmetic_code(),
-            Eq | Gt | Lt => self.generate_comparison_arithmetic_code(),
+            Eq | Gt | Lt => match self.routine {
+                Some(routine) => self.generate_shared_comparison_code(routine),
+                None => self.generate_comparison_arithmetic_code(),
+            },
         }
     }
 }
@@ -0,0 +1,77 @@
+use crate::commands::{functional::FunctionalCommand, Command};
+
+pub struct BootstrapCommand {
+    shared_calls: bool,
+}
+
+impl BootstrapCommand {
+    pub fn new(shared_calls: bool) -> Self {
+        Self { shared_calls }
+    }
+}
+
+impl Command for BootstrapCommand {
+    fn generate(&self) -> Vec<String> {
+        let mut result = vec![
+            // set sp = 256
+            "@256".to_string(),
+            "D=A".to_string(),
+            "@SP".to_string(),
+            "M=D".to_string(),
+        ];
+
+        result.extend(
+            FunctionalCommand::call("Bootstrap", "Sys.init", 0, 0)
+                .shared(self.shared_calls)
+                .generate(),
+        );
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_sp_and_calls_sys_init() {
+        let code = BootstrapCommand::new(false).generate();
+
+        assert_eq!(code[..4], ["@256", "D=A", "@SP", "M=D"]);
+        assert_eq!(code[4], "@Bootstrap$$ret.0");
+
+        // no arguments, so ARG = SP - 5
+        let arg = code.iter().position(|line| line == "@5").unwrap();
+        assert_eq!(
+            code[arg + 1..arg + 6],
+            ["D=A", "@SP", "D=M-D", "@ARG", "M=D"]
+        );
+
+        let jump = code.iter().position(|line| line == "@Sys.init").unwrap();
+        assert_eq!(code[jump + 1..], ["0;JMP", "(Bootstrap$$ret.0)"]);
+    }
+
+    #[test]
+    fn calls_sys_init_through_the_shared_routine() {
+        let code = BootstrapCommand::new(true).generate();
+
+        assert_eq!(code[..4], ["@256", "D=A", "@SP", "M=D"]);
+        assert_eq!(
+            code[4..],
+            [
+                "@Sys.init",
+                "D=A",
+                "@R13",
+                "M=D",
+                "@R14",
+                "M=0",
+                "@Bootstrap$$ret.0",
+                "D=A",
+                "@$$CALL",
+                "0;JMP",
+                "(Bootstrap$$ret.0)",
+            ]
+        );
+    }
+}
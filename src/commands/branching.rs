@@ -1,48 +1,26 @@
-use regex::Regex;
-
 use crate::commands::Command;
 
-enum BranchingCommandType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchingCommandType {
     IfGoto,
     Goto,
     Label,
 }
 
 pub struct BranchingCommand<'a> {
-    label: String,
-    _file_name: &'a str,
-    function_name: Option<&'a str>,
+    label: &'a str,
+    // the enclosing function, or the module outside of one
+    scope: &'a str,
     command_type: BranchingCommandType,
 }
 
 impl<'a> BranchingCommand<'a> {
-    pub fn new(
-        command: &'a str,
-        file_name: &'a str,
-        function_name: Option<&'a str>,
-    ) -> Option<Self> {
-        let patterns = [
-            (BranchingCommandType::IfGoto, r"if-goto\s+(\w+)"),
-            (BranchingCommandType::Goto, r"goto\s+(\w+)"),
-            (BranchingCommandType::Label, r"label\s+(\w+)"),
-        ];
-
-        for (command_type, pattern) in patterns.into_iter() {
-            if let Ok(regex) = Regex::new(pattern) {
-                if let Some(captures) = regex.captures(command) {
-                    if let Some(label) = captures.get(1) {
-                        return Some(Self {
-                            label: label.as_str().to_string(),
-                            _file_name: file_name,
-                            function_name,
-                            command_type,
-                        });
-                    }
-                }
-            }
+    pub fn new(command_type: BranchingCommandType, label: &'a str, scope: &'a str) -> Self {
+        Self {
+            label,
+            scope,
+            command_type,
         }
-
-        None
     }
 }
 
@@ -52,24 +30,20 @@ impl<'a> BranchingCommand<'a> {
             "@SP".to_string(),
             "AM=M-1".to_string(),
             "D=M".to_string(),
-            format!("@{0}${1}", self.function_name.unwrap_or(""), self.label),
+            format!("@{0}${1}", self.scope, self.label),
             "D;JNE".to_string(),
         ]
     }
 
     fn generate_goto(&self) -> Vec<String> {
         vec![
-            format!("@{0}${1}", self.function_name.unwrap_or(""), self.label),
+            format!("@{0}${1}", self.scope, self.label),
             "0;JMP".to_string(),
         ]
     }
 
     fn generate_label(&self) -> Vec<String> {
-        vec![format!(
-            "({0}${1})",
-            self.function_name.unwrap_or(""),
-            self.label
-        )]
+        vec![format!("({0}${1})", self.scope, self.label)]
     }
 }
 
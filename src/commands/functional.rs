@@ -1,66 +1,56 @@
-use regex::Regex;
+use crate::{
+    commands::{shared::SharedRoutine, Command},
+    context::generated_label,
+};
 
-use crate::commands::Command;
-
-enum FunctionalCommandType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FunctionalCommandType {
     Call,
     Function,
     Return,
 }
 
 pub struct FunctionalCommand<'a> {
-    _file_name: &'a str,
     function_name: &'a str,
     calling_function: Option<&'a str>,
     ret: u16,
     arg: u16,
     command_type: FunctionalCommandType,
+    // jump to the shared call and return routines instead of inlining them
+    shared: bool,
 }
 
 impl<'a> FunctionalCommand<'a> {
     pub fn new(
-        command: &'a str,
-        file_name: &'a str,
-        function_name: Option<&'a str>,
+        command_type: FunctionalCommandType,
+        calling_function: Option<&'a str>,
+        arg: u16,
+        function_name: &'a str,
         ret: u16,
-    ) -> Option<Self> {
-        let patterns = [
-            (
-                FunctionalCommandType::Function,
-                r"function\s+([\w\.]+)\s+(\d+)",
-            ),
-            (FunctionalCommandType::Return, r"return"),
-            (FunctionalCommandType::Call, r"call\s+([\w\.]+)\s+(\d+)"),
-        ];
-
-        for (_i, (command_type, pattern)) in patterns.into_iter().enumerate() {
-            if let Ok(regex) = Regex::new(pattern) {
-                if let Some(captures) = regex.captures(command) {
-                    let arg = if let Some(label) = captures.get(2) {
-                        label.as_str().parse().unwrap()
-                    } else {
-                        0
-                    };
-
-                    let calling_function = if let Some(label) = captures.get(1) {
-                        Some(label.as_str())
-                    } else {
-                        None
-                    };
-
-                    return Some(Self {
-                        arg,
-                        calling_function,
-                        command_type,
-                        _file_name: file_name,
-                        function_name: function_name?,
-                        ret,
-                    });
-                }
-            }
+    ) -> Self {
+        Self {
+            arg,
+            calling_function,
+            command_type,
+            function_name,
+            ret,
+            shared: false,
         }
+    }
 
-        None
+    pub fn shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    pub fn call(function_name: &'a str, calling_function: &'a str, arg: u16, ret: u16) -> Self {
+        Self::new(
+            FunctionalCommandType::Call,
+            Some(calling_function),
+            arg,
+            function_name,
+            ret,
+        )
     }
 }
 
@@ -80,10 +70,17 @@ impl<'a> FunctionalCommand<'a> {
             ]);
         }
 
-        return result;
+        result
     }
 
-    fn generate_return_command(&self) -> Vec<String> {
+    fn generate_shared_return_command(&self) -> Vec<String> {
+        vec![
+            format!("@{0}", SharedRoutine::Return.label()),
+            "0;JMP".to_string(),
+        ]
+    }
+
+    pub fn generate_return_command(&self) -> Vec<String> {
         vec![
             // save return address lcl - 5
             "@5".to_string(),
@@ -145,7 +142,7 @@ impl<'a> FunctionalCommand<'a> {
     }
 
     fn generate_call_command(&self) -> Vec<String> {
-        let return_label = format!("{0}$ret.{1}", self.function_name, self.ret);
+        let return_label = generated_label(self.function_name, "ret", self.ret);
         let narg = 5 + self.arg;
         let calling_function = format!("@{0}", self.calling_function.expect("a calling function"));
 
@@ -204,14 +201,67 @@ impl<'a> FunctionalCommand<'a> {
             format!("({return_label})"),
         ]
     }
+
+    // the target goes in R13, nArgs in R14 and the return address in D
+    fn generate_shared_call_command(&self) -> Vec<String> {
+        let return_label = generated_label(self.function_name, "ret", self.ret);
+        let calling_function = format!("@{0}", self.calling_function.expect("a calling function"));
+
+        let mut result = vec![
+            calling_function,
+            "D=A".to_string(),
+            "@R13".to_string(),
+            "M=D".to_string(),
+        ];
+
+        // 0 and 1 are constants of the ALU
+        if self.arg <= 1 {
+            result.extend(["@R14".to_string(), format!("M={0}", self.arg)]);
+        } else {
+            result.extend([
+                format!("@{0}", self.arg),
+                "D=A".to_string(),
+                "@R14".to_string(),
+                "M=D".to_string(),
+            ]);
+        }
+
+        result.extend([
+            format!("@{return_label}"),
+            "D=A".to_string(),
+            format!("@{0}", SharedRoutine::Call.label()),
+            "0;JMP".to_string(),
+            format!("({return_label})"),
+        ]);
+
+        result
+    }
 }
 
 impl<'a> Command for FunctionalCommand<'a> {
     fn generate(&self) -> Vec<String> {
         match self.command_type {
+            FunctionalCommandType::Call if self.shared => self.generate_shared_call_command(),
             FunctionalCommandType::Call => self.generate_call_command(),
             FunctionalCommandType::Function => self.generate_function_command(),
+            FunctionalCommandType::Return if self.shared => self.generate_shared_return_command(),
             FunctionalCommandType::Return => self.generate_return_command(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{output::is_label, translate, Options};
+
+    #[test]
+    fn calls_and_labels_work_outside_of_a_function() {
+        let source =
+            "call Main.f 0\nlabel END\ngoto END\nfunction Main.f 0\npush constant 1\nreturn\n";
+        let output = translate(&[("dir/Main.vm", source)], &Options::default()).unwrap();
+        let labels: Vec<&str> = output.lines().filter(|line| is_label(line)).collect();
+
+        assert_eq!(labels, ["(Main$top$$ret.1)", "(Main$top$END)", "(Main.f)"]);
+        assert!(output.lines().any(|line| line == "@Main$top$END"));
+    }
+}
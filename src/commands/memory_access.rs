@@ -1,5 +1,5 @@
-use regex::Regex;
-enum MemorySegment {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemorySegment {
     Lcl,
     Arg,
     This,
@@ -10,7 +10,8 @@ enum MemorySegment {
     Constant,
 }
 
-enum MemoryCommandType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryCommandType {
     Push,
     Pop,
 }
@@ -20,30 +21,9 @@ use MemorySegment::*;
 
 use crate::commands::Command;
 
-pub struct MemoryAccessCommand<'a> {
-    file_name: &'a str,
-    command_type: MemoryCommandType,
-    segment: MemorySegment,
-    _command: &'a str,
-    i: i16,
-}
-
-impl<'a> MemoryAccessCommand<'a> {
-    pub fn new(command: &'a str, file_name: &'a str) -> Option<Self> {
-        let memory_access_command_pattern = Regex::new(
-            r"^(push|pop)\s+(argument|local|this|that|static|pointer|temp|constant)\s+(\d+)$",
-        )
-        .ok()?;
-
-        let captures = memory_access_command_pattern.captures(command)?;
-
-        let command_type = match &captures[1] {
-            "pop" => Pop,
-            "push" => Push,
-            _ => unreachable!(),
-        };
-
-        let segment = match &captures[2] {
+impl MemorySegment {
+    pub fn parse(segment: &str) -> Option<Self> {
+        let segment = match segment {
             "argument" => Arg,
             "local" => Lcl,
             "this" => This,
@@ -52,24 +32,46 @@ impl<'a> MemoryAccessCommand<'a> {
             "pointer" => Pointer,
             "temp" => Temp,
             "constant" => Constant,
-            _ => unreachable!(),
+            _ => return None,
         };
 
-        let i = (&captures[3]).parse::<i16>().ok()?;
+        Some(segment)
+    }
 
-        if let MemorySegment::Pointer = segment {
-            if i != 0 && i != 1 {
-                return None;
-            }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arg => "argument",
+            Lcl => "local",
+            This => "this",
+            That => "that",
+            Static => "static",
+            Pointer => "pointer",
+            Temp => "temp",
+            Constant => "constant",
         }
+    }
+}
 
-        Some(Self {
-            file_name,
+pub struct MemoryAccessCommand<'a> {
+    module_name: &'a str,
+    command_type: MemoryCommandType,
+    segment: MemorySegment,
+    i: u16,
+}
+
+impl<'a> MemoryAccessCommand<'a> {
+    pub fn new(
+        command_type: MemoryCommandType,
+        segment: MemorySegment,
+        i: u16,
+        module_name: &'a str,
+    ) -> Self {
+        Self {
+            module_name,
             command_type,
             segment,
-            _command: command,
             i,
-        })
+        }
     }
 }
 
@@ -97,14 +99,21 @@ impl<'a> MemoryAccessCommand<'a> {
     }
 
     fn handle_constant_push(&self) -> Vec<String> {
-        vec![
-            format!("@{0}", self.i),
-            "D=A".to_string(),
+        // an A instruction only loads 15 bits, larger words are loaded negated
+        let mut result = if self.i <= i16::MAX as u16 {
+            vec![format!("@{0}", self.i), "D=A".to_string()]
+        } else {
+            vec![format!("@{0}", !self.i), "D=!A".to_string()]
+        };
+
+        result.extend([
             "@SP".to_string(),
             "M=M+1".to_string(),
             "A=M-1".to_string(),
             "M=D".to_string(),
-        ]
+        ]);
+
+        result
     }
 
     fn handle_pointer_push(&self) -> Vec<String> {
@@ -126,7 +135,7 @@ impl<'a> MemoryAccessCommand<'a> {
 
     fn handle_static_push(&self) -> Vec<String> {
         vec![
-            format!("@{0}.{1}", self.file_name, self.i),
+            format!("@{0}.{1}", self.module_name, self.i),
             "D=M".to_string(),
             "@SP".to_string(),
             "M=M+1".to_string(),
@@ -198,7 +207,7 @@ impl<'a> MemoryAccessCommand<'a> {
             "@SP".to_string(),
             "AM=M-1".to_string(),
             "D=M".to_string(),
-            format!("@{0}.{1}", self.file_name, self.i),
+            format!("@{0}.{1}", self.module_name, self.i),
             "M=D".to_string(),
         ]
     }
@@ -0,0 +1,206 @@
+use crate::commands::{
+    arithmetic::ArithmeticType,
+    functional::{FunctionalCommand, FunctionalCommandType},
+    Command,
+};
+
+// code emitted once per program and jumped to from every use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SharedRoutine {
+    // keeps the program from running into the routines
+    Halt,
+    Call,
+    Return,
+    Eq,
+    Gt,
+    Lt,
+}
+
+impl SharedRoutine {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SharedRoutine::Halt => "$$HALT",
+            SharedRoutine::Call => "$$CALL",
+            SharedRoutine::Return => "$$RETURN",
+            SharedRoutine::Eq => "$$EQ",
+            SharedRoutine::Gt => "$$GT",
+            SharedRoutine::Lt => "$$LT",
+        }
+    }
+
+    pub fn comparison(command_type: ArithmeticType) -> Option<Self> {
+        match command_type {
+            ArithmeticType::Eq => Some(SharedRoutine::Eq),
+            ArithmeticType::Gt => Some(SharedRoutine::Gt),
+            ArithmeticType::Lt => Some(SharedRoutine::Lt),
+            _ => None,
+        }
+    }
+
+    fn generate_halt(&self) -> Vec<String> {
+        vec![
+            format!("({0})", self.label()),
+            format!("@{0}", self.label()),
+            "0;JMP".to_string(),
+        ]
+    }
+
+    // the target in R13, nArgs in R14 and the return address in D
+    fn generate_call(&self) -> Vec<String> {
+        let mut result = vec![
+            format!("({0})", self.label()),
+            // push return address
+            "@SP".to_string(),
+            "M=M+1".to_string(),
+            "A=M-1".to_string(),
+            "M=D".to_string(),
+        ];
+
+        for pointer in ["LCL", "ARG", "THIS", "THAT"] {
+            result.extend([
+                format!("@{pointer}"),
+                "D=M".to_string(),
+                "@SP".to_string(),
+                "M=M+1".to_string(),
+                "A=M-1".to_string(),
+                "M=D".to_string(),
+            ]);
+        }
+
+        result.extend([
+            // set arg = sp - 5 - nArgs
+            "@R14".to_string(),
+            "D=M".to_string(),
+            "@5".to_string(),
+            "D=D+A".to_string(),
+            "@SP".to_string(),
+            "D=M-D".to_string(),
+            "@ARG".to_string(),
+            "M=D".to_string(),
+            // set lcl = sp
+            "@SP".to_string(),
+            "D=M".to_string(),
+            "@LCL".to_string(),
+            "M=D".to_string(),
+            // goto the target
+            "@R13".to_string(),
+            "A=M".to_string(),
+            "0;JMP".to_string(),
+        ]);
+
+        result
+    }
+
+    fn generate_return(&self) -> Vec<String> {
+        let mut result = vec![format!("({0})", self.label())];
+
+        result.extend(
+            FunctionalCommand::new(FunctionalCommandType::Return, None, 0, "", 0)
+                .generate_return_command(),
+        );
+
+        result
+    }
+
+    // the return address in D, replaces x and y on the stack with x op y
+    fn generate_comparison(&self) -> Vec<String> {
+        let jump = match self {
+            SharedRoutine::Eq => "D;JEQ",
+            SharedRoutine::Gt => "D;JGT",
+            SharedRoutine::Lt => "D;JLT",
+            _ => unreachable!(),
+        };
+        let end_label = format!("{0}.END", self.label());
+
+        vec![
+            format!("({0})", self.label()),
+            // save return address
+            "@R15".to_string(),
+            "M=D".to_string(),
+            // d = x - y, a at x
+            "@SP".to_string(),
+            "AM=M-1".to_string(),
+            "D=M".to_string(),
+            "A=A-1".to_string(),
+            "D=M-D".to_string(),
+            // true unless the jump is not taken
+            "M=-1".to_string(),
+            format!("@{end_label}"),
+            jump.to_string(),
+            "@SP".to_string(),
+            "A=M-1".to_string(),
+            "M=0".to_string(),
+            format!("({end_label})"),
+            // return
+            "@R15".to_string(),
+            "A=M".to_string(),
+            "0;JMP".to_string(),
+        ]
+    }
+}
+
+impl Command for SharedRoutine {
+    fn generate(&self) -> Vec<String> {
+        match self {
+            SharedRoutine::Halt => self.generate_halt(),
+            SharedRoutine::Call => self.generate_call(),
+            SharedRoutine::Return => self.generate_return(),
+            SharedRoutine::Eq | SharedRoutine::Gt | SharedRoutine::Lt => self.generate_comparison(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, translate_modules, verify::verify, Options};
+
+    // counts down from 3 recursively, comparing on the way
+    const SOURCE: &str = "function Sys.init 0\npush constant 3\ncall Sys.count 1\npop static 0\n\
+                          label END\ngoto END\n\
+                          function Sys.count 0\npush argument 0\npush constant 0\neq\n\
+                          if-goto DONE\npush argument 0\npush constant 1\nsub\ncall Sys.count 1\n\
+                          push argument 0\nadd\n\
+                          push argument 0\npush constant 0\ngt\npop temp 0\n\
+                          push argument 0\npush constant 0\nlt\npop temp 1\n\
+                          push argument 0\npush constant 9\nlt\npop temp 2\n\
+                          push argument 0\npush constant 9\nlt\npop temp 3\n\
+                          push argument 0\npush constant 9\nlt\npop temp 4\n\
+                          return\nlabel DONE\npush constant 0\nreturn\n";
+
+    fn run(options: Options) -> Vec<String> {
+        let options = Options {
+            bootstrap: true,
+            ..options
+        };
+        let modules = parse(&[("Sys.vm", SOURCE)], &options).unwrap();
+        let output = translate_modules(&modules, &options);
+        let verification = verify(&modules, &output, &options, &[], 10_000).unwrap();
+
+        assert!(verification.halted);
+        output
+            .lines()
+            .filter(|line| line.starts_with("($$"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn shared_calls_match_the_vm() {
+        let routines = run(Options {
+            shared_calls: true,
+            ..Options::default()
+        });
+
+        assert_eq!(routines, ["($$HALT)", "($$CALL)", "($$RETURN)"]);
+    }
+
+    #[test]
+    fn shared_comparisons_match_the_vm() {
+        let routines = run(Options {
+            shared_comparisons: true,
+            ..Options::default()
+        });
+
+        assert_eq!(routines, ["($$HALT)", "($$LT)", "($$LT.END)"]);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    commands::{arithmetic::ArithmeticType, shared::SharedRoutine},
+    instruction::VmInstruction,
+    module::ModuleName,
+};
+
+pub struct Context {
+    pub module_name: ModuleName,
+    pub function_name: Option<String>,
+    top_level: String,
+    // the shared routines the program jumps to instead of inlining their code
+    pub routines: Vec<SharedRoutine>,
+    ret: u16,
+    cmp: u16,
+}
+
+impl Context {
+    pub fn new(module_name: ModuleName, routines: Vec<SharedRoutine>) -> Self {
+        Self {
+            top_level: module_name.top_level(),
+            module_name,
+            function_name: None,
+            routines,
+            ret: 0,
+            cmp: 0,
+        }
+    }
+
+    // moves the label counters past the given instruction, before it is built
+    pub fn advance(&mut self, instruction: &VmInstruction) {
+        match instruction {
+            VmInstruction::Function(name, _) => self.enter_function(name),
+            VmInstruction::Call(..) => self.ret += 1,
+            VmInstruction::Arithmetic(
+                ArithmeticType::Eq | ArithmeticType::Gt | ArithmeticType::Lt,
+            ) => self.cmp += 1,
+            _ => {}
+        }
+    }
+
+    pub fn enter_function(&mut self, function_name: &str) {
+        self.function_name = Some(function_name.to_string());
+        self.ret = 0;
+        self.cmp = 0;
+    }
+
+    pub fn shares(&self, routine: SharedRoutine) -> bool {
+        self.routines.contains(&routine)
+    }
+
+    pub fn ret(&self) -> u16 {
+        self.ret
+    }
+
+    // labels are scoped by the enclosing function, or by the top level of the module
+    // outside of one
+    pub fn scope(&self) -> &str {
+        self.function_name.as_deref().unwrap_or(&self.top_level)
+    }
+
+    pub fn comparison_label(&self) -> String {
+        generated_label(self.scope(), "CMP", self.cmp)
+    }
+}
+
+// the symbol of a label the translator makes up, `$$` can't appear in a VM label or
+// function name, so it never clashes with the `{scope}${label}` of a label in the source
+pub fn generated_label(scope: &str, kind: &str, n: u16) -> String {
+    format!("{scope}$${kind}.{n}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        commands::{arithmetic::ArithmeticCommand, Command},
+        output::is_label,
+        translate, Options,
+    };
+
+    #[test]
+    fn numbers_comparison_labels_per_function() {
+        let eq = VmInstruction::Arithmetic(ArithmeticType::Eq);
+        let mut context = Context::new(ModuleName::from_path("dir/Main.vm"), vec![]);
+        context.advance(&eq);
+        assert_eq!(context.comparison_label(), "Main$top$$CMP.1");
+
+        context.advance(&VmInstruction::Function("Main.f".to_string(), 0));
+        context.advance(&eq);
+        context.advance(&eq);
+        assert_eq!(context.comparison_label(), "Main.f$$CMP.2");
+
+        let code = ArithmeticCommand::new(ArithmeticType::Lt, &context).generate();
+        assert_eq!(code[7..9], ["@Main.f$$CMP.2", "D;JLT"]);
+        assert_eq!(code.last().unwrap(), "(Main.f$$CMP.2.END)");
+        assert_eq!(
+            code,
+            ArithmeticCommand::new(ArithmeticType::Lt, &context).generate()
+        );
+    }
+
+    #[test]
+    fn generated_labels_dont_clash_with_vm_labels() {
+        let source = "function C.main 0\npush constant 1\npush constant 1\neq\ncall C.f 0\n\
+                      label CMP.1\nlabel ret.1\nreturn\nfunction C.f 0\npush constant 0\nreturn\n";
+        let output = translate(&[("C.vm", source)], &Options::default()).unwrap();
+        let labels: Vec<&str> = output.lines().filter(|line| is_label(line)).collect();
+
+        for label in [
+            "(C.main$$CMP.1)",
+            "(C.main$CMP.1)",
+            "(C.main$$ret.1)",
+            "(C.main$ret.1)",
+        ] {
+            assert!(labels.contains(&label), "{label}");
+        }
+        assert_eq!(labels.iter().collect::<HashSet<_>>().len(), labels.len());
+    }
+}
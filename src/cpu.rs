@@ -0,0 +1,172 @@
+pub const RAM_SIZE: usize = 32768;
+
+pub struct Cpu {
+    pub rom: Vec<u16>,
+    pub ram: Vec<i16>,
+    pub a: i16,
+    pub d: i16,
+    pub pc: u16,
+    pub cycles: u64,
+    pub halted: bool,
+}
+
+impl Cpu {
+    pub fn new(rom: Vec<u16>) -> Self {
+        Self {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            cycles: 0,
+            halted: false,
+        }
+    }
+
+    pub fn run(&mut self, max_cycles: u64) {
+        while !self.halted && self.cycles < max_cycles {
+            self.step();
+        }
+    }
+
+    pub fn step(&mut self) {
+        let Some(&word) = self.rom.get(self.pc as usize) else {
+            self.halted = true;
+            return;
+        };
+
+        self.cycles += 1;
+
+        if word & 0x8000 == 0 {
+            self.a = word as i16;
+            self.pc += 1;
+            return;
+        }
+
+        // the jump goes to the A from before the instruction, like the address of M
+        let address = self.address();
+        let y = if word & 0x1000 != 0 {
+            self.ram[address]
+        } else {
+            self.a
+        };
+        let out = Self::compute((word >> 6) & 0b111111, self.d, y);
+
+        if word & 0b001000 != 0 {
+            self.ram[address] = out;
+        }
+
+        if word & 0b100000 != 0 {
+            self.a = out;
+        }
+
+        if word & 0b010000 != 0 {
+            self.d = out;
+        }
+
+        let jump = word & 0b111;
+        let taken = (jump & 0b100 != 0 && out < 0)
+            || (jump & 0b010 != 0 && out == 0)
+            || (jump & 0b001 != 0 && out > 0);
+
+        if taken {
+            let target = address as u16;
+
+            // `(LOOP) @LOOP 0;JMP` never leaves
+            if jump == 0b111
+                && word & 0b111000 == 0
+                && target + 1 == self.pc
+                && self.rom[target as usize] == target
+            {
+                self.halted = true;
+            }
+
+            self.pc = target;
+        } else {
+            self.pc += 1;
+        }
+    }
+
+    // only the low 15 bits of A address memory
+    fn address(&self) -> usize {
+        (self.a as u16 & 0x7fff) as usize
+    }
+
+    fn compute(comp: u16, x: i16, y: i16) -> i16 {
+        let (zx, nx, zy, ny, f, no) = (
+            comp & 0b100000 != 0,
+            comp & 0b010000 != 0,
+            comp & 0b001000 != 0,
+            comp & 0b000100 != 0,
+            comp & 0b000010 != 0,
+            comp & 0b000001 != 0,
+        );
+
+        let x = if zx { 0 } else { x };
+        let x = if nx { !x } else { x };
+        let y = if zy { 0 } else { y };
+        let y = if ny { !y } else { y };
+        let out = if f { x.wrapping_add(y) } else { x & y };
+
+        if no {
+            !out
+        } else {
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn run(source: &str, cycles: u64) -> Cpu {
+        let mut cpu = Cpu::new(assemble("test.asm", source.lines()).unwrap().words);
+
+        for _ in 0..cycles {
+            cpu.step();
+        }
+
+        cpu
+    }
+
+    #[test]
+    fn computes_into_memory() {
+        let cpu = run("@2\nD=A\n@3\nD=D+A\n@20\nM=D\nM=M-1", 7);
+        assert_eq!((cpu.a, cpu.d, cpu.ram[20], cpu.pc), (20, 5, 4, 7));
+    }
+
+    #[test]
+    fn halts_on_an_endless_loop() {
+        let mut cpu = Cpu::new(
+            assemble("test.asm", "(END)\n@END\n0;JMP".lines())
+                .unwrap()
+                .words,
+        );
+        cpu.run(100);
+        assert!(cpu.halted);
+    }
+
+    #[test]
+    fn jumps_to_a_from_before_the_instruction() {
+        let cpu = run("@5\nD=0\nA=D;JEQ", 3);
+        assert_eq!((cpu.pc, cpu.a), (5, 0));
+
+        let mut cpu = Cpu::new(
+            assemble("test.asm", "@6\nAM=M-1;JMP".lines())
+                .unwrap()
+                .words,
+        );
+        cpu.ram[6] = 100;
+        cpu.step();
+        cpu.step();
+        assert_eq!((cpu.pc, cpu.a, cpu.ram[6]), (6, 99, 99));
+    }
+
+    #[test]
+    fn writes_m_at_a_from_before_the_instruction() {
+        let cpu = run("@7\nD=A\nAM=D+1", 3);
+        assert_eq!((cpu.a, cpu.ram[7], cpu.ram[8]), (8, 8, 0));
+    }
+}
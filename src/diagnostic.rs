@@ -0,0 +1,171 @@
+use std::fmt;
+
+pub struct Diagnostic {
+    pub file: Option<Box<str>>,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub message: String,
+    pub hint: Option<Box<str>>,
+    pub notes: Vec<String>,
+    pub source_line: Option<Box<str>>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            line: 0,
+            column: 0,
+            length: 0,
+            message: message.into(),
+            hint: None,
+            notes: vec![],
+            source_line: None,
+        }
+    }
+
+    // line and column are 1-based, the span covers `length` characters of `source_line`
+    pub fn at(
+        mut self,
+        file: &str,
+        line: usize,
+        column: usize,
+        length: usize,
+        source_line: &str,
+    ) -> Self {
+        self.file = Some(file.into());
+        self.line = line;
+        self.column = column;
+        self.length = length;
+        self.source_line = Some(source_line.into());
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into().into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: {}", self.message)?;
+
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        if let Some(file) = &self.file {
+            writeln!(f, "{gutter}--> {file}:{0}:{1}", self.line, self.column)?;
+        }
+
+        if let Some(source_line) = &self.source_line {
+            // keep tabs so the marker lines up with the source
+            let padding: String = source_line
+                .chars()
+                .take(self.column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            writeln!(f, "{gutter} |")?;
+            writeln!(f, "{0} | {source_line}", self.line)?;
+            writeln!(f, "{gutter} | {padding}{0}", "^".repeat(self.length.max(1)))?;
+
+            if self.hint.is_some() || !self.notes.is_empty() {
+                writeln!(f, "{gutter} |")?;
+            }
+        }
+
+        for note in &self.notes {
+            writeln!(f, "{gutter} = note: {note}")?;
+        }
+
+        if let Some(hint) = &self.hint {
+            writeln!(f, "{gutter} = help: {hint}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.0 {
+            writeln!(f, "{diagnostic}")?;
+        }
+
+        if self.0.len() > 1 {
+            writeln!(
+                f,
+                "error: could not translate due to {0} previous errors",
+                self.0.len()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_at_the_source() {
+        let diagnostic = Diagnostic::new("unknown segment `locl`")
+            .at("Main.vm", 12, 6, 4, "push locl 0")
+            .with_note("a note")
+            .with_hint("a hint");
+
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: unknown segment `locl`\n  \
+             --> Main.vm:12:6\n   \
+             |\n\
+             12 | push locl 0\n   \
+             |      ^^^^\n   \
+             |\n   \
+             = note: a note\n   \
+             = help: a hint\n"
+        );
+    }
+
+    #[test]
+    fn keeps_tabs_in_the_marker_line() {
+        let diagnostic = Diagnostic::new("message").at("Main.vm", 1, 2, 3, "\tadd");
+
+        assert!(diagnostic.to_string().contains("1 | \tadd\n  | \t^^^\n"));
+    }
+
+    #[test]
+    fn counts_several_errors() {
+        let diagnostics = Diagnostics(vec![Diagnostic::new("a"), Diagnostic::new("b")]);
+
+        assert_eq!(
+            diagnostics.to_string(),
+            "error: a\n\nerror: b\n\nerror: could not translate due to 2 previous errors\n"
+        );
+    }
+}
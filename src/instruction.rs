@@ -0,0 +1,45 @@
+use std::fmt;
+
+use crate::commands::{arithmetic::ArithmeticType, memory_access::MemorySegment};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VmInstruction {
+    Arithmetic(ArithmeticType),
+    Push(MemorySegment, u16),
+    Pop(MemorySegment, u16),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function(String, u16),
+    Call(String, u16),
+    Return,
+}
+
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub kind: VmInstruction,
+    pub span: Span,
+}
+
+impl fmt::Display for VmInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmInstruction::Arithmetic(command_type) => write!(f, "{}", command_type.as_str()),
+            VmInstruction::Push(segment, i) => write!(f, "push {0} {i}", segment.as_str()),
+            VmInstruction::Pop(segment, i) => write!(f, "pop {0} {i}", segment.as_str()),
+            VmInstruction::Label(label) => write!(f, "label {label}"),
+            VmInstruction::Goto(label) => write!(f, "goto {label}"),
+            VmInstruction::IfGoto(label) => write!(f, "if-goto {label}"),
+            VmInstruction::Function(name, n) => write!(f, "function {name} {n}"),
+            VmInstruction::Call(name, n) => write!(f, "call {name} {n}"),
+            VmInstruction::Return => write!(f, "return"),
+        }
+    }
+}
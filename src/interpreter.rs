@@ -0,0 +1,420 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    commands::{arithmetic::ArithmeticType, memory_access::MemorySegment},
+    diagnostic::Diagnostic,
+    instruction::{Instruction, VmInstruction},
+    module::Module,
+};
+
+pub const RAM_SIZE: usize = 32768;
+pub const SP: usize = 0;
+pub const LCL: usize = 1;
+pub const ARG: usize = 2;
+pub const THIS: usize = 3;
+pub const THAT: usize = 4;
+pub const TEMP: usize = 5;
+pub const STACK: usize = 256;
+
+pub struct ProgramInstruction<'a> {
+    pub module: usize,
+    pub instruction: &'a Instruction,
+    pub function: Option<&'a str>,
+}
+
+pub struct Frame<'a> {
+    pub function: &'a str,
+    pub lcl: usize,
+}
+
+pub struct Interpreter<'a> {
+    pub modules: &'a [Module],
+    pub program: Vec<ProgramInstruction<'a>>,
+    labels: HashMap<String, usize>,
+    functions: HashMap<&'a str, usize>,
+    pub ram: Vec<i16>,
+    // keyed by module index and static index
+    pub statics: BTreeMap<(usize, u16), i16>,
+    pub frames: Vec<Frame<'a>>,
+    pub pc: usize,
+    pub steps: u64,
+    pub halted: bool,
+    // addresses written through segments and the stack, when tracked
+    pub written: Option<Vec<usize>>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(modules: &'a [Module]) -> Self {
+        let mut program = vec![];
+        let mut labels = HashMap::new();
+        let mut functions = HashMap::new();
+
+        for (module_index, module) in modules.iter().enumerate() {
+            let mut function = None;
+
+            for instruction in &module.instructions {
+                match &instruction.kind {
+                    VmInstruction::Function(name, _) => {
+                        function = Some(name.as_str());
+                        functions.entry(name.as_str()).or_insert(program.len());
+                    }
+                    VmInstruction::Label(label) => {
+                        labels
+                            .entry(Self::label_key(module, function, label))
+                            .or_insert(program.len());
+                    }
+                    _ => {}
+                }
+
+                program.push(ProgramInstruction {
+                    module: module_index,
+                    instruction,
+                    function,
+                });
+            }
+        }
+
+        let mut ram = vec![0; RAM_SIZE];
+        ram[SP] = STACK as i16;
+
+        Self {
+            modules,
+            program,
+            labels,
+            functions,
+            ram,
+            statics: BTreeMap::new(),
+            frames: vec![],
+            pc: 0,
+            steps: 0,
+            halted: false,
+            written: None,
+        }
+    }
+
+    // the symbol of the label in the translated code
+    fn label_key(module: &Module, function: Option<&str>, label: &str) -> String {
+        match function {
+            Some(function) => format!("{function}${label}"),
+            None => format!("{0}${label}", module.name.top_level()),
+        }
+    }
+
+    // same as the translated bootstrap, returning from Sys.init falls into the first instruction
+    pub fn bootstrap(&mut self) -> Result<(), Diagnostic> {
+        self.ram[SP] = STACK as i16;
+        self.call("Sys.init", 0, 0)
+            .map_err(|message| Diagnostic::new(format!("bootstrap failed: {message}")))
+    }
+
+    pub fn run(&mut self, max_steps: u64) -> Result<(), Diagnostic> {
+        while !self.halted && self.steps < max_steps {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<(), Diagnostic> {
+        let Some(current) = self.program.get(self.pc) else {
+            self.halted = true;
+            return Ok(());
+        };
+        let (module, instruction, function) =
+            (current.module, current.instruction, current.function);
+
+        self.execute(module, &instruction.kind, function)
+            .map_err(|message| self.modules[module].diagnostic(instruction.span, message))?;
+        self.steps += 1;
+
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        module: usize,
+        instruction: &'a VmInstruction,
+        function: Option<&'a str>,
+    ) -> Result<(), String> {
+        let mut next = self.pc + 1;
+
+        match instruction {
+            VmInstruction::Arithmetic(command_type) => self.arithmetic(*command_type)?,
+            VmInstruction::Push(segment, i) => {
+                let value = match segment {
+                    MemorySegment::Constant => *i as i16,
+                    MemorySegment::Static => self.statics.get(&(module, *i)).copied().unwrap_or(0),
+                    _ => self.read(self.segment_address(*segment, *i)?)?,
+                };
+
+                self.push(value)?;
+            }
+            VmInstruction::Pop(segment, i) => {
+                let value = self.pop()?;
+
+                match segment {
+                    MemorySegment::Constant => {}
+                    MemorySegment::Static => {
+                        self.statics.insert((module, *i), value);
+                    }
+                    _ => self.write(self.segment_address(*segment, *i)?, value)?,
+                }
+            }
+            VmInstruction::Label(_) => {}
+            VmInstruction::Goto(label) => {
+                next = self.jump(module, function, label)?;
+
+                // a goto back over nothing but labels loops forever, an if-goto can still fall
+                // through once its condition pops a zero
+                if next <= self.pc
+                    && self.program[next..self.pc]
+                        .iter()
+                        .all(|p| matches!(p.instruction.kind, VmInstruction::Label(_)))
+                {
+                    self.halted = true;
+                }
+            }
+            VmInstruction::IfGoto(label) => {
+                if self.pop()? != 0 {
+                    next = self.jump(module, function, label)?;
+                }
+            }
+            VmInstruction::Function(_, n) => {
+                for _ in 0..*n {
+                    self.push(0)?;
+                }
+            }
+            VmInstruction::Call(name, n) => {
+                self.call(name, *n, self.pc + 1)?;
+                next = self.pc;
+            }
+            VmInstruction::Return => next = self.ret()?,
+        }
+
+        self.pc = next;
+
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, command_type: ArithmeticType) -> Result<(), String> {
+        let y = self.pop()?;
+
+        let value = match command_type {
+            ArithmeticType::Neg => y.wrapping_neg(),
+            ArithmeticType::Not => !y,
+            _ => {
+                let x = self.pop()?;
+
+                match command_type {
+                    ArithmeticType::Add => x.wrapping_add(y),
+                    ArithmeticType::Sub => x.wrapping_sub(y),
+                    ArithmeticType::And => x & y,
+                    ArithmeticType::Or => x | y,
+                    ArithmeticType::Eq => -((x == y) as i16),
+                    ArithmeticType::Gt => -((x > y) as i16),
+                    ArithmeticType::Lt => -((x < y) as i16),
+                    ArithmeticType::Neg | ArithmeticType::Not => unreachable!(),
+                }
+            }
+        };
+
+        self.push(value)
+    }
+
+    fn jump(&self, module: usize, function: Option<&str>, label: &str) -> Result<usize, String> {
+        self.labels
+            .get(&Self::label_key(&self.modules[module], function, label))
+            .copied()
+            .ok_or_else(|| format!("undefined label `{label}`"))
+    }
+
+    fn call(&mut self, name: &str, n: u16, return_address: usize) -> Result<(), String> {
+        let (&function, &target) = self
+            .functions
+            .get_key_value(name)
+            .ok_or_else(|| format!("undefined function `{name}`"))?;
+
+        self.push(return_address as i16)?;
+
+        for pointer in [LCL, ARG, THIS, THAT] {
+            self.push(self.ram[pointer])?;
+        }
+
+        let sp = self.ram[SP];
+        self.ram[ARG] = sp.wrapping_sub(5).wrapping_sub(n as i16);
+        self.ram[LCL] = sp;
+        self.frames.push(Frame {
+            function,
+            lcl: sp as u16 as usize,
+        });
+        self.pc = target;
+
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<usize, String> {
+        let frame = self.ram[LCL] as u16 as usize;
+
+        if frame < 5 {
+            return Err(format!("no call frame to return from (LCL = {frame})"));
+        }
+
+        let return_address = self.read(frame - 5)? as u16 as usize;
+        let value = self.pop()?;
+        let arg = self.ram[ARG] as u16 as usize;
+
+        self.write(arg, value)?;
+        self.ram[SP] = (arg + 1) as i16;
+
+        for (pointer, offset) in [(THAT, 1), (THIS, 2), (ARG, 3), (LCL, 4)] {
+            self.ram[pointer] = self.read(frame - offset)?;
+        }
+
+        self.frames.pop();
+
+        Ok(return_address)
+    }
+
+    fn segment_address(&self, segment: MemorySegment, i: u16) -> Result<usize, String> {
+        let base = match segment {
+            MemorySegment::Lcl => self.ram[LCL] as u16 as usize,
+            MemorySegment::Arg => self.ram[ARG] as u16 as usize,
+            MemorySegment::This => self.ram[THIS] as u16 as usize,
+            MemorySegment::That => self.ram[THAT] as u16 as usize,
+            MemorySegment::Pointer => THIS,
+            MemorySegment::Temp => TEMP,
+            MemorySegment::Static | MemorySegment::Constant => unreachable!(),
+        };
+
+        Ok(base + i as usize)
+    }
+
+    pub fn read(&self, address: usize) -> Result<i16, String> {
+        self.ram
+            .get(address)
+            .copied()
+            .ok_or_else(|| format!("read outside of RAM at address {address}"))
+    }
+
+    pub fn write(&mut self, address: usize, value: i16) -> Result<(), String> {
+        *self
+            .ram
+            .get_mut(address)
+            .ok_or_else(|| format!("write outside of RAM at address {address}"))? = value;
+
+        if let Some(written) = &mut self.written {
+            written.push(address);
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, value: i16) -> Result<(), String> {
+        let sp = self.ram[SP] as u16 as usize;
+
+        self.write(sp, value)
+            .map_err(|_| format!("stack overflow (SP = {sp})"))?;
+        self.ram[SP] = (sp + 1) as i16;
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i16, String> {
+        let sp = (self.ram[SP] as u16 as usize)
+            .checked_sub(1)
+            .ok_or("stack underflow (SP = 0)")?;
+
+        self.ram[SP] = sp as i16;
+        self.read(sp)
+    }
+
+    // the symbol the translator uses for a static variable
+    pub fn static_name(&self, module: usize, i: u16) -> String {
+        format!("{0}.{i}", self.modules[module].name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Options};
+
+    fn modules(sources: &[(&str, &str)]) -> Vec<Module> {
+        parse(sources, &Options::default()).unwrap()
+    }
+
+    #[test]
+    fn evaluates_arithmetic() {
+        let modules = modules(&[(
+            "Main.vm",
+            "push constant 7\npush constant 8\nadd\npush constant 3\nlt\n\
+             push constant 5\nneg\npush constant 32767\npush constant 1\nadd\n",
+        )]);
+        let mut vm = Interpreter::new(&modules);
+
+        vm.run(100).unwrap();
+
+        assert!(vm.halted);
+        assert_eq!(vm.ram[SP], 259);
+        assert_eq!(&vm.ram[STACK..259], [0, -5, i16::MIN]);
+    }
+
+    #[test]
+    fn calls_and_returns() {
+        let modules = modules(&[(
+            "Sys.vm",
+            "function Sys.init 0\npush constant 3\ncall Sys.double 1\n\
+                 pop static 0\nlabel END\ngoto END\n\
+                 function Sys.double 1\npush argument 0\npush argument 0\nadd\nreturn\n",
+        )]);
+        let mut vm = Interpreter::new(&modules);
+
+        vm.bootstrap().unwrap();
+        vm.run(100).unwrap();
+
+        assert!(vm.halted);
+        assert_eq!(vm.statics[&(0, 0)], 6);
+        assert_eq!(vm.frames.len(), 1);
+        assert_eq!(vm.frames[0].function, "Sys.init");
+    }
+
+    #[test]
+    fn reports_stack_underflow_at_the_instruction() {
+        let modules = modules(&[("Main.vm", "push constant 1\nadd\n")]);
+        let mut vm = Interpreter::new(&modules);
+        vm.ram[SP] = 0;
+
+        let error = vm.run(100).err().unwrap();
+
+        assert_eq!(error.message, "stack underflow (SP = 0)");
+    }
+
+    #[test]
+    fn tracks_written_addresses() {
+        let modules = modules(&[(
+            "Main.vm",
+            "push constant 3030\npop pointer 0\npush constant 1\npop this 2\n",
+        )]);
+        let mut vm = Interpreter::new(&modules);
+        vm.written = Some(vec![]);
+
+        vm.run(100).unwrap();
+
+        assert_eq!(vm.ram[3032], 1);
+        assert_eq!(vm.written.unwrap(), [256, THIS, 256, 3032]);
+    }
+
+    #[test]
+    fn only_an_unconditional_jump_halts() {
+        let modules = modules(&[(
+            "Main.vm",
+            "push constant 0\npush constant 1\npush constant 1\nlabel L\nif-goto L\n\
+             push constant 9\n",
+        )]);
+        let mut vm = Interpreter::new(&modules);
+
+        vm.run(100).unwrap();
+
+        assert_eq!(&vm.ram[STACK..vm.ram[SP] as usize], [9]);
+    }
+}
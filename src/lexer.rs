@@ -0,0 +1,106 @@
+#[derive(Clone, Copy)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    // 1-based, in characters
+    pub column: usize,
+}
+
+pub struct Line<'a> {
+    pub number: usize,
+    pub text: &'a str,
+    pub tokens: Vec<Token<'a>>,
+}
+
+pub struct Lexer<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            lines: source.lines().enumerate(),
+        }
+    }
+
+    fn tokenize(text: &'a str) -> Vec<Token<'a>> {
+        let mut tokens = vec![];
+        let mut start = None;
+        let mut column = 0;
+
+        for (offset, c) in text.char_indices() {
+            column += 1;
+
+            if c == '/' && text[offset..].starts_with("//") {
+                break;
+            }
+
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some((offset, column)),
+                (true, Some((from, from_column))) => {
+                    tokens.push(Token {
+                        text: &text[from..offset],
+                        column: from_column,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((from, from_column)) = start {
+            let end = text.find("//").unwrap_or(text.len());
+
+            tokens.push(Token {
+                text: &text[from..end],
+                column: from_column,
+            });
+        }
+
+        tokens
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Line<'a>;
+
+    // skips blank and comment-only lines
+    fn next(&mut self) -> Option<Self::Item> {
+        for (n, text) in self.lines.by_ref() {
+            let tokens = Self::tokenize(text);
+
+            if !tokens.is_empty() {
+                return Some(Line {
+                    number: n + 1,
+                    text,
+                    tokens,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens<'a>(line: &Line<'a>) -> Vec<(&'a str, usize)> {
+        line.tokens
+            .iter()
+            .map(|token| (token.text, token.column))
+            .collect()
+    }
+
+    #[test]
+    fn splits_lines_into_tokens_with_columns() {
+        let lines: Vec<Line> =
+            Lexer::new("// header\n\n  push  constant 7 // seven\npop\tlocal 0//x\n").collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].number, 3);
+        assert_eq!(tokens(&lines[0]), [("push", 3), ("constant", 9), ("7", 18)]);
+        assert_eq!(lines[1].number, 4);
+        assert_eq!(tokens(&lines[1]), [("pop", 1), ("local", 5), ("0", 11)]);
+    }
+}
@@ -0,0 +1,265 @@
+pub mod assembler;
+pub mod budget;
+pub mod commands;
+pub mod context;
+pub mod cpu;
+pub mod diagnostic;
+pub mod instruction;
+pub mod interpreter;
+pub mod lexer;
+pub mod link;
+pub mod module;
+pub mod optimizer;
+pub mod output;
+pub mod parser;
+pub mod script;
+pub mod source_map;
+pub mod stats;
+pub mod verify;
+
+use std::collections::BTreeMap;
+
+use commands::{
+    arithmetic::{ArithmeticCommand, ArithmeticType},
+    bootstrap::BootstrapCommand,
+    shared::SharedRoutine,
+    Command, CommandType,
+};
+use context::Context;
+use diagnostic::{Diagnostic, Diagnostics};
+use instruction::VmInstruction;
+use module::{Module, ModuleName};
+use output::{Block, Location, Output};
+use parser::Parser;
+
+#[derive(Clone, Default)]
+pub struct Options {
+    pub bootstrap: bool,
+    pub optimize: bool,
+    // one call and one return routine for the whole program instead of inline code
+    pub shared_calls: bool,
+    // shared eq, gt and lt routines, each only where it makes the program smaller
+    pub shared_comparisons: bool,
+    // accept out of range temp indices and `pop constant` like older versions did
+    pub lenient: bool,
+    // `push constant` with negative values and 16-bit values past 32767
+    pub extended_constants: bool,
+}
+
+pub fn parse(sources: &[(&str, &str)], options: &Options) -> Result<Vec<Module>, Diagnostics> {
+    let modules = parse_unlinked(sources, options)?;
+
+    link::check(&modules, options)?;
+
+    Ok(modules)
+}
+
+// without resolving labels and calls across the files, for when some of the program is
+// missing
+pub fn parse_unlinked(
+    sources: &[(&str, &str)],
+    options: &Options,
+) -> Result<Vec<Module>, Diagnostics> {
+    let mut modules = vec![];
+    let mut diagnostics = vec![];
+
+    for (name, text) in sources {
+        match Parser::new(name, text)
+            .lenient(options.lenient)
+            .extended_constants(options.extended_constants)
+            .parse()
+        {
+            Ok(module) => modules.push(module),
+            Err(errors) => diagnostics.extend(errors),
+        }
+    }
+
+    diagnostics.extend(duplicate_modules(sources));
+
+    if !diagnostics.is_empty() {
+        return Err(Diagnostics(diagnostics));
+    }
+
+    Ok(modules)
+}
+
+// files with the same stem would share their statics and top level labels
+fn duplicate_modules(sources: &[(&str, &str)]) -> Vec<Diagnostic> {
+    let mut paths: BTreeMap<ModuleName, Vec<&str>> = BTreeMap::new();
+
+    for (path, _) in sources {
+        paths
+            .entry(ModuleName::from_path(path))
+            .or_default()
+            .push(path);
+    }
+
+    paths
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(name, paths)| {
+            paths
+                .iter()
+                .fold(
+                    Diagnostic::new(format!(
+                        "{0} input files have the module name `{name}`",
+                        paths.len()
+                    )),
+                    |diagnostic, path| diagnostic.with_note(format!("`{path}`")),
+                )
+                .with_hint("statics and labels are named after the file name without its extension, rename all but one of the files")
+        })
+        .collect()
+}
+
+pub fn translate(sources: &[(&str, &str)], options: &Options) -> Result<Output, Diagnostics> {
+    let modules = parse(sources, options)?;
+    let output = translate_modules(&modules, options);
+
+    budget::check(&modules, &output)?;
+
+    Ok(output)
+}
+
+pub fn translate_modules(modules: &[Module], options: &Options) -> Output {
+    let mut blocks = vec![];
+    let routines = shared_routines(modules, options);
+
+    if options.bootstrap {
+        blocks.push(Block {
+            comment: "bootstrap".to_string(),
+            code: BootstrapCommand::new(routines.contains(&SharedRoutine::Call)).generate(),
+            location: None,
+        });
+    }
+
+    for (module_index, module) in modules.iter().enumerate() {
+        let mut context = Context::new(module.name.clone(), routines.clone());
+
+        for (instruction_index, instruction) in module.instructions.iter().enumerate() {
+            context.advance(&instruction.kind);
+
+            blocks.push(Block {
+                comment: module.text(instruction.span).to_string(),
+                code: CommandType::build(&instruction.kind, &context).generate(),
+                location: Some(Location {
+                    module: module_index,
+                    instruction: instruction_index,
+                }),
+            });
+        }
+    }
+
+    if !routines.is_empty() {
+        for routine in [SharedRoutine::Halt].iter().chain(&routines) {
+            blocks.push(Block {
+                comment: routine.label().to_string(),
+                code: routine.generate(),
+                location: None,
+            });
+        }
+    }
+
+    let mut output = Output { blocks };
+
+    if options.optimize {
+        optimizer::optimize(&mut output);
+    }
+
+    output
+}
+
+// the shared routines the program jumps to
+fn shared_routines(modules: &[Module], options: &Options) -> Vec<SharedRoutine> {
+    let mut routines = vec![];
+    let instructions = || modules.iter().flat_map(|module| &module.instructions);
+
+    if options.shared_calls {
+        if options.bootstrap || instructions().any(|i| matches!(i.kind, VmInstruction::Call(..))) {
+            routines.push(SharedRoutine::Call);
+        }
+
+        if instructions().any(|i| i.kind == VmInstruction::Return) {
+            routines.push(SharedRoutine::Return);
+        }
+    }
+
+    if options.shared_comparisons {
+        for command_type in [ArithmeticType::Eq, ArithmeticType::Gt, ArithmeticType::Lt] {
+            let uses = instructions()
+                .filter(|i| i.kind == VmInstruction::Arithmetic(command_type))
+                .count();
+            let routine = SharedRoutine::comparison(command_type).expect("a comparison");
+            let size =
+                |code: Vec<String>| code.iter().filter(|line| !output::is_label(line)).count();
+            let command_size = |routines| {
+                let context = Context::new(ModuleName::from_path(""), routines);
+                size(ArithmeticCommand::new(command_type, &context).generate())
+            };
+
+            // only when the routine, and the halt loop in front of it, cost less than inlining
+            let mut shared = uses * command_size(vec![routine]) + size(routine.generate());
+
+            if routines.is_empty() {
+                shared += size(SharedRoutine::Halt.generate());
+            }
+
+            if shared < uses * command_size(vec![]) {
+                routines.push(routine);
+            }
+        }
+    }
+
+    routines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_errors_from_every_input() {
+        let sources = [
+            ("A.vm", "push constant 1\nfoo\npush locl 0\n"),
+            ("B.vm", "pop\n"),
+        ];
+        let errors = parse(&sources, &Options::default()).err().unwrap();
+        let located: Vec<_> = errors
+            .0
+            .iter()
+            .map(|e| (e.file.as_deref(), e.line, e.message.as_str()))
+            .collect();
+
+        assert_eq!(
+            located,
+            [
+                (Some("A.vm"), 2, "unknown command `foo`"),
+                (Some("A.vm"), 3, "unknown segment `locl`"),
+                (Some("B.vm"), 1, "missing operands for `pop`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn translates_sources_in_memory() {
+        let output = translate(&[("Main.vm", "push constant 7\n")], &Options::default()).unwrap();
+
+        assert_eq!(
+            output.to_string(),
+            "// push constant 7\n@7\nD=A\n@SP\nM=M+1\nA=M-1\nM=D\n"
+        );
+    }
+
+    #[test]
+    fn parse_unlinked_leaves_calls_unresolved() {
+        let sources = [(
+            "Main.vm",
+            "function Main.main 0\ncall Math.multiply 2\nreturn\n",
+        )];
+
+        assert!(parse_unlinked(&sources, &Options::default()).is_ok());
+
+        let errors = parse(&sources, &Options::default()).err().unwrap();
+        assert_eq!(errors.0[0].message, "undefined function `Math.multiply`");
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use crate::{
+    assembler::PREDEFINED,
+    diagnostic::{Diagnostic, Diagnostics},
+    instruction::{Instruction, VmInstruction},
+    module::{self, Module},
+    Options,
+};
+
+// where a function or label is defined, by module and instruction
+type Definition<'a> = (&'a Module, &'a Instruction);
+
+// resolves the targets of every `goto`, `if-goto` and `call` across all files, and
+// rejects functions and labels that are defined twice or functions whose label would
+// be an assembler symbol
+pub fn check(modules: &[Module], options: &Options) -> Result<(), Diagnostics> {
+    let enclosing = module::functions(modules);
+    let mut diagnostics = vec![];
+    let mut functions: BTreeMap<&str, Definition> = BTreeMap::new();
+    // keyed by the scope, the enclosing function or the top level of the module
+    let mut labels: BTreeMap<(String, &str), Definition> = BTreeMap::new();
+
+    let scope = |module_index: usize, instruction_index: usize| match enclosing[module_index]
+        [instruction_index]
+    {
+        Some(function) => function.to_string(),
+        None => modules[module_index].name.top_level(),
+    };
+
+    for (module_index, module) in modules.iter().enumerate() {
+        for (instruction_index, instruction) in module.instructions.iter().enumerate() {
+            if let VmInstruction::Function(name, _) = &instruction.kind {
+                if let Some(symbol) = symbol_clash(modules, name) {
+                    diagnostics.push(
+                        module
+                            .diagnostic(
+                                instruction.span,
+                                format!("function `{name}` has the name of {symbol}"),
+                            )
+                            .with_hint("the assembler would take the function for that symbol"),
+                    );
+                }
+            }
+
+            let duplicate = match &instruction.kind {
+                VmInstruction::Function(name, _) => match functions.entry(name) {
+                    Entry::Occupied(first) => Some((format!("function `{name}`"), *first.get())),
+                    Entry::Vacant(entry) => {
+                        entry.insert((module, instruction));
+                        None
+                    }
+                },
+                VmInstruction::Label(label) => {
+                    match labels.entry((scope(module_index, instruction_index), label)) {
+                        Entry::Occupied(first) => Some((format!("label `{label}`"), *first.get())),
+                        Entry::Vacant(entry) => {
+                            entry.insert((module, instruction));
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some((what, first)) = duplicate {
+                diagnostics.push(
+                    module
+                        .diagnostic(
+                            instruction.span,
+                            format!("{what} is defined more than once"),
+                        )
+                        .with_note(format!("first defined at {0}", position(first))),
+                );
+            }
+        }
+    }
+
+    for (module_index, module) in modules.iter().enumerate() {
+        for (instruction_index, instruction) in module.instructions.iter().enumerate() {
+            match &instruction.kind {
+                VmInstruction::Goto(label) | VmInstruction::IfGoto(label) => {
+                    let scope = scope(module_index, instruction_index);
+
+                    if labels.contains_key(&(scope.clone(), label.as_str())) {
+                        continue;
+                    }
+
+                    let mut diagnostic = module
+                        .diagnostic(instruction.span, format!("undefined label `{label}`"))
+                        .with_note(format!("`{scope}` has no `label {label}`"));
+
+                    // the same label in another function isn't visible from here
+                    if let Some(((other, _), _)) =
+                        labels.iter().find(|((_, other), _)| *other == label)
+                    {
+                        diagnostic = diagnostic.with_note(format!(
+                            "`{other}` has a label `{label}`, but labels are only visible \
+                             within their function"
+                        ));
+                    }
+
+                    diagnostics.push(diagnostic);
+                }
+                VmInstruction::Call(name, _) if !functions.contains_key(name.as_str()) => {
+                    diagnostics.push(
+                        module
+                            .diagnostic(instruction.span, format!("undefined function `{name}`"))
+                            .with_hint(format!(
+                                "no input file declares `function {name} <nLocals>`"
+                            )),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if options.bootstrap && !functions.contains_key("Sys.init") {
+        diagnostics.push(
+            Diagnostic::new("the bootstrap calls `Sys.init`, but no input file defines it")
+                .with_hint(
+                    "add `function Sys.init 0` in `Sys.vm`, or translate with `--no-bootstrap`",
+                ),
+        );
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(Diagnostics(diagnostics));
+    }
+
+    Ok(())
+}
+
+// the translated function is the label `({name})`, which can't share its name with a
+// predefined symbol or with a static variable, `{Module}.{i}` for any of the files
+fn symbol_clash(modules: &[Module], name: &str) -> Option<String> {
+    if PREDEFINED.iter().any(|(symbol, _)| *symbol == name) {
+        return Some("a predefined assembler symbol".to_string());
+    }
+
+    let (module_name, index) = name.rsplit_once('.')?;
+    index.parse::<u16>().ok()?;
+
+    modules
+        .iter()
+        .find(|module| module.name.as_str() == module_name)
+        .map(|module| format!("a static variable of {0}", module.path))
+}
+
+fn position((module, instruction): Definition) -> String {
+    format!(
+        "{0}:{1}:{2}",
+        module.path, instruction.span.line, instruction.span.column
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, Options};
+
+    fn messages(sources: &[(&str, &str)], options: &Options) -> Vec<String> {
+        match parse(sources, options) {
+            Ok(_) => vec![],
+            Err(errors) => errors.0.into_iter().map(|e| e.message).collect(),
+        }
+    }
+
+    #[test]
+    fn resolves_calls_across_files() {
+        let sources = [
+            (
+                "Main.vm",
+                "function Main.main 0\ncall Math.double 1\nreturn\n",
+            ),
+            (
+                "Math.vm",
+                "function Math.double 0\npush argument 0\npush argument 0\nadd\nreturn\n",
+            ),
+        ];
+
+        assert!(messages(&sources, &Options::default()).is_empty());
+    }
+
+    #[test]
+    fn rejects_undefined_targets() {
+        let sources = [(
+            "Main.vm",
+            "function Main.main 0\ngoto END\ncall Math.double 1\nreturn\n",
+        )];
+
+        assert_eq!(
+            messages(&sources, &Options::default()),
+            ["undefined label `END`", "undefined function `Math.double`"]
+        );
+    }
+
+    #[test]
+    fn labels_are_scoped_to_their_function() {
+        let sources = [(
+            "Main.vm",
+            "function Main.a 0\nlabel LOOP\nreturn\nfunction Main.b 0\ngoto LOOP\nreturn\n",
+        )];
+
+        assert_eq!(
+            messages(&sources, &Options::default()),
+            ["undefined label `LOOP`"]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_definitions() {
+        let sources = [
+            (
+                "Main.vm",
+                "function Main.main 0\nlabel A\nlabel A\nreturn\n",
+            ),
+            ("Other.vm", "function Main.main 0\nreturn\n"),
+        ];
+
+        assert_eq!(
+            messages(&sources, &Options::default()),
+            [
+                "label `A` is defined more than once",
+                "function `Main.main` is defined more than once"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_functions_named_like_assembler_symbols() {
+        let sources = [
+            (
+                "Foo.vm",
+                "function SP 0\nreturn\nfunction R13 0\nreturn\nfunction Foo.3 0\nreturn\n",
+            ),
+            (
+                "Bar.vm",
+                "function SCREEN2 0\nreturn\nfunction Bar.x3 0\nreturn\n",
+            ),
+        ];
+        let errors = parse(&sources, &Options::default()).err().unwrap().0;
+        let errors: Vec<(&str, usize)> = errors
+            .iter()
+            .map(|e| (e.message.as_str(), e.line))
+            .collect();
+
+        assert_eq!(
+            errors,
+            [
+                (
+                    "function `SP` has the name of a predefined assembler symbol",
+                    1
+                ),
+                (
+                    "function `R13` has the name of a predefined assembler symbol",
+                    3
+                ),
+                (
+                    "function `Foo.3` has the name of a static variable of Foo.vm",
+                    5
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn bootstrap_needs_sys_init() {
+        let sources = [("Main.vm", "function Main.main 0\nreturn\n")];
+        let options = Options {
+            bootstrap: true,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            messages(&sources, &options),
+            ["the bootstrap calls `Sys.init`, but no input file defines it"]
+        );
+    }
+}
@@ -1,25 +1,478 @@
-use std::{env, fs::File, io::Write};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use args_parser::{Arguments, Emit, Format, Mode};
+use hack_isc_vm_translator::{
+    assembler, budget,
+    commands::arithmetic::ArithmeticType,
+    cpu::Cpu,
+    diagnostic::{Diagnostic, Diagnostics},
+    instruction::VmInstruction,
+    interpreter::{self, Interpreter},
+    module::Module,
+    parse, parse_unlinked,
+    script::{self, Machine, Script},
+    source_map,
+    stats::Stats,
+    translate, translate_modules, verify, Options,
+};
 
 mod args_parser;
-mod commands;
-mod parser;
 
 fn main() {
-    let mut args = args_parser::Arguments::build(env::args()).unwrap();
-    let mut output_file = File::create(args.output_file_path).unwrap();
+    if let Err(diagnostics) = run() {
+        eprint!("{diagnostics}");
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Diagnostics> {
+    let args = Arguments::build(env::args()).map_err(|d| Diagnostics(vec![d]))?;
+
+    if args.mode == Mode::Test {
+        return test_scripts(&args);
+    }
+
+    let sources = read_sources(&args)?;
+    let sources: Vec<(&str, &str)> = sources
+        .iter()
+        .map(|(name, text)| (name.as_str(), text.as_str()))
+        .collect();
+
+    match args.mode {
+        Mode::Translate => translate_sources(&args, &sources),
+        Mode::Run => run_sources(&args, &sources),
+        Mode::Emulate => emulate_sources(&args, &sources),
+        Mode::Verify => verify_sources(&args, &sources),
+        Mode::Test => unreachable!(),
+    }
+}
+
+fn read_sources(args: &Arguments) -> Result<Vec<(String, String)>, Diagnostics> {
+    let mut sources = vec![];
+    let mut diagnostics = vec![];
+
+    for input_file in &args.input_file_paths {
+        match fs::read_to_string(input_file) {
+            Ok(text) => sources.push((input_file.display().to_string(), text)),
+            Err(e) => diagnostics.push(Diagnostic::new(format!(
+                "could not read `{}`: {e}",
+                input_file.display()
+            ))),
+        }
+    }
+
+    if !diagnostics.is_empty() {
+        // still report the syntax errors in the files that could be read, calls into the
+        // missing files would only show up as undefined functions
+        if let Err(errors) = parse_unlinked(
+            &sources
+                .iter()
+                .map(|(name, text)| (name.as_str(), text.as_str()))
+                .collect::<Vec<_>>(),
+            &args.options(),
+        ) {
+            diagnostics.extend(errors.0);
+        }
+
+        return Err(Diagnostics(diagnostics));
+    }
+
+    Ok(sources)
+}
+
+fn translate_sources(args: &Arguments, sources: &[(&str, &str)]) -> Result<(), Diagnostics> {
+    let modules = parse(sources, &args.options())?;
+    let output = translate_modules(&modules, &args.options());
+
+    if args.size_report {
+        print_size_report(args, &modules);
+    }
+
+    match args.stats {
+        Some(Format::Text) => print!("{0}", Stats::build(&modules, &output).table()),
+        Some(Format::Json) => print!("{0}", Stats::build(&modules, &output).json()),
+        None => {}
+    }
+
+    budget::check(&modules, &output)?;
+
+    let text = match args.emit {
+        Emit::Asm => output.render(&modules, args.comments),
+        Emit::Hack => {
+            assembler::assemble(&args.output_file_path.display().to_string(), output.lines())
+                .map_err(fail)?
+                .hack()
+        }
+    };
+
+    write(&args.output_file_path, &text)?;
+
+    if let Some(format) = args.map {
+        let entries = source_map::build(
+            &modules,
+            &output,
+            (args.emit == Emit::Asm).then_some(args.comments),
+        );
+        let text = match format {
+            Format::Text => source_map::text(&entries),
+            Format::Json => source_map::json(&entries),
+        };
 
-    for input_file in &mut args.input_file_paths {
-        let mut parser = parser::Parser::new(input_file).unwrap();
+        write(&args.output_file_path.with_extension("map"), &text)?;
+    }
+
+    Ok(())
+}
+
+fn write(path: &Path, text: &str) -> Result<(), Diagnostics> {
+    fs::write(path, text).map_err(|e| {
+        fail(Diagnostic::new(format!(
+            "could not write `{}`: {e}",
+            path.display()
+        )))
+    })
+}
+
+// the program size with inline and with shared call, return and comparison code
+fn print_size_report(args: &Arguments, modules: &[Module]) {
+    let instructions = || modules.iter().flat_map(|module| &module.instructions);
+    let calls = instructions()
+        .filter(|i| matches!(i.kind, VmInstruction::Call(..)))
+        .count()
+        + args.bootstrap as usize;
+    let returns = instructions()
+        .filter(|i| i.kind == VmInstruction::Return)
+        .count();
+    let comparisons = instructions()
+        .filter(|i| {
+            matches!(
+                i.kind,
+                VmInstruction::Arithmetic(
+                    ArithmeticType::Eq | ArithmeticType::Gt | ArithmeticType::Lt
+                )
+            )
+        })
+        .count();
+    let size = |shared| {
+        translate_modules(
+            modules,
+            &Options {
+                shared_calls: shared,
+                shared_comparisons: shared,
+                ..args.options()
+            },
+        )
+        .size()
+    };
+    let (inline, shared) = (size(false), size(true));
+
+    println!("{calls} call sites, {returns} returns, {comparisons} comparisons");
+    println!("inline: {inline} instructions");
+    println!(
+        "shared: {shared} instructions ({0:+} instructions, {1:+.1}%)",
+        shared as i64 - inline as i64,
+        (shared as f64 - inline as f64) * 100.0 / inline.max(1) as f64
+    );
+}
+
+fn run_sources(args: &Arguments, sources: &[(&str, &str)]) -> Result<(), Diagnostics> {
+    let modules = parse(sources, &args.options())?;
+    let mut vm = Interpreter::new(&modules);
+
+    // before the bootstrap like `emulate`, where the bootstrap code runs after them
+    for &(address, value) in &args.ram_values {
+        vm.write(address, value)
+            .map_err(|e| fail(Diagnostic::new(e)))?;
+    }
+
+    if args.bootstrap {
+        vm.bootstrap().map_err(fail)?;
+    }
+
+    let result = vm.run(args.steps);
+
+    if vm.halted {
+        println!("halted after {0} steps", vm.steps);
+    } else {
+        println!("stopped after {0} steps", vm.steps);
+    }
+
+    if !vm.frames.is_empty() {
+        let frames: Vec<&str> = vm.frames.iter().map(|frame| frame.function).collect();
+        println!("call stack: {0}", frames.join(" > "));
+    }
+
+    let statics = vm
+        .statics
+        .iter()
+        .map(|(&(module, i), &value)| (vm.static_name(module, i), value))
+        .collect();
+
+    print_state(args, &vm.ram, statics)?;
+
+    result.map_err(fail)
+}
+
+fn emulate_sources(args: &Arguments, sources: &[(&str, &str)]) -> Result<(), Diagnostics> {
+    let output = translate(sources, &args.options())?;
+    let program = assembler::assemble(&args.output_file_path.display().to_string(), output.lines())
+        .map_err(fail)?;
+    let mut cpu = Cpu::new(program.words);
 
-        while parser.has_more_command() {
-            let (c, command) = parser.advance();
-            let results = command.generate();
+    // start from the same state as the interpreter
+    cpu.ram[interpreter::SP] = interpreter::STACK as i16;
 
-            writeln!(output_file, "// {}", c).unwrap();
+    for &(address, value) in &args.ram_values {
+        *cpu.ram.get_mut(address).ok_or_else(|| {
+            fail(Diagnostic::new(format!(
+                "write outside of RAM at address {address}"
+            )))
+        })? = value;
+    }
+
+    cpu.run(args.steps);
+
+    if cpu.halted {
+        println!("halted after {0} cycles", cpu.cycles);
+    } else {
+        println!("stopped after {0} cycles", cpu.cycles);
+    }
+
+    let statics = program
+        .variables
+        .into_iter()
+        .map(|(name, address)| (name, cpu.ram[address as usize]))
+        .collect();
+
+    print_state(args, &cpu.ram, statics)
+}
 
-            for i in &results {
-                writeln!(output_file, "{}", i).unwrap();
+fn verify_sources(args: &Arguments, sources: &[(&str, &str)]) -> Result<(), Diagnostics> {
+    let modules = parse(sources, &args.options())?;
+    let options = args.options();
+    let output = translate_modules(&modules, &options);
+
+    budget::check(&modules, &output)?;
+
+    let verification =
+        verify::verify(&modules, &output, &options, &args.ram_values, args.steps).map_err(fail)?;
+
+    println!(
+        "ok: {0} steps and {1} cycles agree at {2} checkpoints ({3})",
+        verification.steps,
+        verification.cycles,
+        verification.checkpoints,
+        if verification.halted {
+            "halted"
+        } else {
+            "stopped"
+        }
+    );
+
+    Ok(())
+}
+
+fn test_scripts(args: &Arguments) -> Result<(), Diagnostics> {
+    let mut paths = vec![];
+
+    for path in &args.input_file_paths {
+        find_scripts(path, &mut paths)?;
+    }
+
+    if paths.is_empty() {
+        return Err(fail(Diagnostic::new("no `.tst` test scripts found")));
+    }
+
+    let mut failed = 0;
+
+    for path in &paths {
+        match test_script(path, args) {
+            Ok(true) => println!("PASS {0}", path.display()),
+            Ok(false) => println!("DONE {0} (nothing to compare)", path.display()),
+            Err(diagnostics) => {
+                println!("FAIL {0}", path.display());
+                eprint!("{diagnostics}");
+                failed += 1;
             }
         }
     }
+
+    println!("{0} passed, {failed} failed", paths.len() - failed);
+
+    if failed > 0 {
+        return Err(fail(Diagnostic::new(format!(
+            "{failed} of {0} test scripts failed",
+            paths.len()
+        ))));
+    }
+
+    Ok(())
+}
+
+// `.tst` files, searching directories recursively in name order
+fn find_scripts(path: &Path, scripts: &mut Vec<PathBuf>) -> Result<(), Diagnostics> {
+    if !path.is_dir() {
+        scripts.push(path.to_owned());
+        return Ok(());
+    }
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)
+        .map_err(|e| {
+            fail(Diagnostic::new(format!(
+                "could not read directory `{}`: {e}",
+                path.display()
+            )))
+        })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            find_scripts(&entry, scripts)?;
+        } else if entry.extension().is_some_and(|ext| ext == "tst") {
+            scripts.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+// runs a script against the `.vm` files next to it, true when it had a comparison file
+fn test_script(path: &Path, args: &Arguments) -> Result<bool, Diagnostics> {
+    let read = |path: &Path| {
+        fs::read_to_string(path).map_err(|e| {
+            fail(Diagnostic::new(format!(
+                "could not read `{}`: {e}",
+                path.display()
+            )))
+        })
+    };
+    let script = Script::parse(&path.display().to_string(), &read(path)?).map_err(fail)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let load = script.load.clone().unwrap_or_default();
+
+    // a `.vm` file is loaded alone, the assembly and the directory come from every `.vm` file
+    let input_file_paths = if load.ends_with(".vm") {
+        vec![directory.join(&load)]
+    } else {
+        Arguments::expand_directory(if directory == Path::new("") {
+            Path::new(".")
+        } else {
+            directory
+        })
+        .map_err(fail)?
+    };
+
+    if input_file_paths.is_empty() {
+        return Err(fail(Diagnostic::new(format!(
+            "no `.vm` files to test next to `{}`",
+            path.display()
+        ))));
+    }
+
+    let mut sources = vec![];
+
+    for input_file in &input_file_paths {
+        sources.push((input_file.display().to_string(), read(input_file)?));
+    }
+
+    let sources: Vec<(&str, &str)> = sources
+        .iter()
+        .map(|(name, text)| (name.as_str(), text.as_str()))
+        .collect();
+    let modules = parse(&sources, &args.options())?;
+    let bootstrap = input_file_paths
+        .iter()
+        .any(|path| path.file_name().is_some_and(|name| name == "Sys.vm"));
+
+    let mut machine = if load.ends_with(".asm") || load.ends_with(".hack") {
+        let options = Options {
+            bootstrap,
+            ..args.options()
+        };
+        let output = translate_modules(&modules, &options);
+
+        budget::check(&modules, &output)?;
+
+        let program = assembler::assemble(&load, output.lines()).map_err(fail)?;
+
+        Machine::Cpu(Cpu::new(program.words))
+    } else {
+        let mut vm = Interpreter::new(&modules);
+
+        if bootstrap {
+            vm.bootstrap().map_err(fail)?;
+        }
+
+        Machine::Vm(vm)
+    };
+
+    let output = script.run(&mut machine).map_err(fail)?;
+
+    if let Some(output_file) = &script.output_file {
+        write(&directory.join(output_file), &output)?;
+    }
+
+    let Some(compare_to) = &script.compare_to else {
+        return Ok(false);
+    };
+    let compare_to = directory.join(compare_to);
+
+    script::compare(&output, &read(&compare_to)?).map_err(|diagnostic| {
+        fail(diagnostic.with_note(format!("compared with `{}`", compare_to.display())))
+    })?;
+
+    Ok(true)
+}
+
+fn fail(diagnostic: Diagnostic) -> Diagnostics {
+    Diagnostics(vec![diagnostic])
+}
+
+fn print_state(
+    args: &Arguments,
+    ram: &[i16],
+    statics: Vec<(String, i16)>,
+) -> Result<(), Diagnostics> {
+    println!(
+        "SP={0} LCL={1} ARG={2} THIS={3} THAT={4}",
+        ram[interpreter::SP],
+        ram[interpreter::LCL],
+        ram[interpreter::ARG],
+        ram[interpreter::THIS],
+        ram[interpreter::THAT]
+    );
+
+    let sp = (ram[interpreter::SP] as u16 as usize).min(ram.len());
+    let stack: Vec<String> = ram[interpreter::STACK.min(sp)..sp]
+        .iter()
+        .map(|v| v.to_string())
+        .collect();
+    println!("stack: [{0}]", stack.join(", "));
+
+    for (name, value) in statics {
+        println!("{name} = {value}");
+    }
+
+    for range in &args.ram_ranges {
+        let values = ram.get(range.clone()).ok_or_else(|| {
+            fail(Diagnostic::new(format!(
+                "RAM range {0}..{1} is outside of RAM (0..{2})",
+                range.start,
+                range.end,
+                ram.len()
+            )))
+        })?;
+
+        for (address, value) in range.clone().zip(values) {
+            println!("RAM[{address}] = {value}");
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,189 @@
+use std::{
+    fmt::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    diagnostic::Diagnostic,
+    instruction::{Instruction, Span, VmInstruction},
+};
+
+// the name a file's statics and top level labels are scoped by, the file name without
+// its extension like the reference tools use
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ModuleName(String);
+
+impl ModuleName {
+    pub fn from_path(path: &str) -> Self {
+        Self(
+            Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.to_string()),
+        )
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // the synthetic function that code before the first function of the file belongs to,
+    // `$` keeps it apart from real function names
+    pub fn top_level(&self) -> String {
+        format!("{0}$top", self.0)
+    }
+}
+
+impl fmt::Display for ModuleName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+pub struct Module {
+    pub path: String,
+    // shown in reports
+    pub file_name: String,
+    pub name: ModuleName,
+    pub source: String,
+    pub line_offsets: Vec<usize>,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Module {
+    pub fn new(
+        path: String,
+        file_name: String,
+        source: String,
+        instructions: Vec<Instruction>,
+    ) -> Self {
+        let line_offsets = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            name: ModuleName::from_path(&path),
+            path,
+            file_name,
+            source,
+            line_offsets,
+            instructions,
+        }
+    }
+
+    // 1-based
+    pub fn line(&self, n: usize) -> &str {
+        let start = self.line_offsets[n - 1];
+        let end = self
+            .line_offsets
+            .get(n)
+            .copied()
+            .unwrap_or(self.source.len());
+
+        self.source[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    // the instruction as written in the source
+    pub fn text(&self, span: Span) -> &str {
+        let line = self.line(span.line);
+        let mut offsets = line
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(line.len()));
+        let start = offsets.nth(span.column - 1).unwrap_or(line.len());
+        let end = offsets.nth(span.length - 1).unwrap_or(line.len());
+
+        &line[start..end]
+    }
+
+    pub fn diagnostic(&self, span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(message).at(
+            &self.path,
+            span.line,
+            span.column,
+            span.length,
+            self.line(span.line),
+        )
+    }
+}
+
+// the enclosing function of every instruction, by module and instruction index
+pub fn functions(modules: &[Module]) -> Vec<Vec<Option<&str>>> {
+    modules
+        .iter()
+        .map(|module| {
+            let mut function = None;
+
+            module
+                .instructions
+                .iter()
+                .map(|instruction| {
+                    if let VmInstruction::Function(name, _) = &instruction.kind {
+                        function = Some(name.as_str());
+                    }
+
+                    function
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// the text as a JSON string literal, for the reports
+pub fn json_string(text: &str) -> String {
+    let mut result = String::from('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(result, "\\u{0:04x}", c as u32);
+            }
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, translate, Options};
+
+    #[test]
+    fn names_modules_after_the_file_stem() {
+        assert_eq!(ModuleName::from_path("dir/Foo.vm").as_str(), "Foo");
+        assert_eq!(ModuleName::from_path("Foo").as_str(), "Foo");
+        assert_eq!(ModuleName::from_path("Foo.vm").top_level(), "Foo$top");
+    }
+
+    #[test]
+    fn statics_use_the_module_name() {
+        let output = translate(&[("dir/Foo.vm", "push static 3\n")], &Options::default()).unwrap();
+
+        assert!(output.lines().any(|line| line == "@Foo.3"));
+    }
+
+    #[test]
+    fn rejects_files_with_the_same_module_name() {
+        let sources = [
+            ("a/Foo.vm", "push constant 1\n"),
+            ("b/Foo.vm", "push constant 2\n"),
+        ];
+        let errors = parse(&sources, &Options::default()).err().unwrap();
+
+        assert_eq!(
+            errors.0[0].message,
+            "2 input files have the module name `Foo`"
+        );
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("a\"b\\c\nd\t"), "\"a\\\"b\\\\c\\nd\\u0009\"");
+    }
+}
@@ -0,0 +1,365 @@
+use crate::output::{is_label, Output};
+
+// what a Hack instruction reads and writes, `M` as an operand also reads A
+#[derive(Default)]
+struct Effect {
+    reads_a: bool,
+    reads_d: bool,
+    writes_a: bool,
+    writes_d: bool,
+    writes_m: bool,
+    jumps: bool,
+}
+
+impl Effect {
+    fn of(line: &str) -> Self {
+        if line.starts_with('@') {
+            return Self {
+                writes_a: true,
+                ..Self::default()
+            };
+        }
+
+        let (dest, rest) = line.split_once('=').unwrap_or(("", line));
+        let (comp, jump) = rest.split_once(';').unwrap_or((rest, ""));
+
+        Self {
+            reads_a: comp.contains(['A', 'M']) || dest.contains('M') || !jump.is_empty(),
+            reads_d: comp.contains('D'),
+            writes_a: dest.contains('A'),
+            writes_d: dest.contains('D'),
+            writes_m: dest.contains('M'),
+            jumps: !jump.is_empty(),
+        }
+    }
+}
+
+// what A is known to hold
+#[derive(Clone, PartialEq, Eq)]
+enum Known {
+    Symbol(String),
+    // RAM[SP] plus an offset, the top of the stack for -1
+    Stack(i8),
+}
+
+// the lines of all blocks with the index of the block they came from
+type Lines = Vec<(usize, String)>;
+
+// the code replacing a matched run of lines, if it matches
+type Rewrite = fn(&[&str]) -> Option<Vec<String>>;
+
+// rewrites the code within straight-line runs of instructions, never across a label
+// or past a jump; removed and replaced lines stay attributed to their block
+pub fn optimize(output: &mut Output) {
+    let mut lines: Lines = output
+        .blocks
+        .iter_mut()
+        .enumerate()
+        .flat_map(|(i, block)| {
+            std::mem::take(&mut block.code)
+                .into_iter()
+                .map(move |line| (i, line))
+        })
+        .collect();
+
+    loop {
+        let size = lines.len();
+
+        cancel_push_pop(&mut lines);
+        drop_known_loads(&mut lines);
+        drop_dead_writes(&mut lines);
+
+        if lines.len() == size {
+            break;
+        }
+    }
+
+    for (block, line) in lines {
+        output.blocks[block].code.push(line);
+    }
+}
+
+const PUSH_D: [&str; 4] = ["@SP", "M=M+1", "A=M-1", "M=D"];
+
+// a push of D directly followed by a pop leaves the stack as it was
+fn cancel_push_pop(lines: &mut Lines) {
+    const REWRITES: [(usize, Rewrite); 2] = [(7, pop_into_d), (16, pop_into_segment)];
+
+    let mut kept: Lines = Vec::with_capacity(lines.len());
+
+    for (block, line) in std::mem::take(lines) {
+        kept.push((block, line));
+
+        for (size, rewrite) in REWRITES {
+            let Some(start) = kept.len().checked_sub(size) else {
+                continue;
+            };
+            let code: Vec<&str> = kept[start..]
+                .iter()
+                .map(|(_, line)| line.as_str())
+                .collect();
+
+            if let Some(code) = rewrite(&code) {
+                kept.truncate(start);
+                kept.extend(code.into_iter().map(|line| (block, line)));
+                break;
+            }
+        }
+    }
+
+    *lines = kept;
+}
+
+// into D, only A still has to point at the top as the pop left it
+fn pop_into_d(code: &[&str]) -> Option<Vec<String>> {
+    let (push, pop) = code.split_at(PUSH_D.len());
+
+    (push == PUSH_D && pop == ["@SP", "AM=M-1", "D=M"])
+        .then(|| vec!["@SP".to_string(), "A=M".to_string()])
+}
+
+// into a segment, which `handle_segment_pop` does by keeping the address in R13 while it pops;
+// instead D waits in R13 while the address is worked out, and R13 then holds their sum so
+// both can be taken back out of it
+fn pop_into_segment(code: &[&str]) -> Option<Vec<String>> {
+    let (push, pop) = code.split_at(PUSH_D.len());
+    let (address, rest) = pop.split_at(4);
+    let [index, "D=A", segment, "D=D+M"] = address else {
+        return None;
+    };
+
+    if push != PUSH_D
+        || rest != ["@R13", "M=D", "@SP", "AM=M-1", "D=M", "@R13", "A=M", "M=D"]
+        || !matches!(*segment, "@LCL" | "@ARG" | "@THIS" | "@THAT")
+        || index.strip_prefix('@')?.parse::<u16>().is_err()
+    {
+        return None;
+    }
+
+    let mut result = vec![
+        "@R13".to_string(),
+        "M=D".to_string(),
+        segment.to_string(),
+        "D=M".to_string(),
+    ];
+
+    if *index != "@0" {
+        result.extend([index.to_string(), "D=D+A".to_string()]);
+    }
+
+    result.extend([
+        "@R13".to_string(),
+        "M=D+M".to_string(),
+        "D=M-D".to_string(),
+        "A=M-D".to_string(),
+        "M=D".to_string(),
+    ]);
+
+    Some(result)
+}
+
+// drops `@X` when A already holds X, and `@SP A=M-1` when A already points at the top
+fn drop_known_loads(lines: &mut Lines) {
+    let mut known: Option<Known> = None;
+    let mut kept = Vec::with_capacity(lines.len());
+    let mut iterator = std::mem::take(lines).into_iter().peekable();
+
+    while let Some((block, line)) = iterator.next() {
+        if is_label(&line) {
+            known = None;
+        } else if let Some(symbol) = line.strip_prefix('@') {
+            if known == Some(Known::Symbol(symbol.to_string())) {
+                continue;
+            }
+
+            if symbol == "SP" {
+                let offset = match iterator.peek().map(|(_, line)| line.as_str()) {
+                    Some("A=M") => Some(0),
+                    Some("A=M-1") => Some(-1),
+                    _ => None,
+                };
+
+                if offset.is_some() && known == offset.map(Known::Stack) {
+                    iterator.next();
+                    continue;
+                }
+            }
+
+            known = Some(Known::Symbol(symbol.to_string()));
+        } else if Effect::of(&line).writes_a {
+            let at_sp = known == Some(Known::Symbol("SP".to_string()));
+
+            known = match line.split_once('=') {
+                // A and SP get the same new value
+                Some((dest, _)) if at_sp && dest.contains('M') => Some(Known::Stack(0)),
+                Some((_, "M")) if at_sp => Some(Known::Stack(0)),
+                Some((_, "M-1")) if at_sp => Some(Known::Stack(-1)),
+                _ => None,
+            };
+        }
+
+        // writing M through a stack address leaves SP alone, and after `M=M+1` at SP
+        // A still holds the address of SP
+        kept.push((block, line));
+    }
+
+    *lines = kept;
+}
+
+// drops instructions whose only effect is writing A or D when the value is never read
+fn drop_dead_writes(lines: &mut Lines) {
+    let (mut live_a, mut live_d) = (true, true);
+    let mut dead = vec![false; lines.len()];
+
+    for (i, (_, line)) in lines.iter().enumerate().rev() {
+        if is_label(line) {
+            (live_a, live_d) = (true, true);
+            continue;
+        }
+
+        let effect = Effect::of(line);
+
+        if effect.jumps {
+            (live_a, live_d) = (true, true);
+        } else if !effect.writes_m
+            && (effect.writes_a || effect.writes_d)
+            && !(effect.writes_a && live_a)
+            && !(effect.writes_d && live_d)
+        {
+            dead[i] = true;
+            continue;
+        }
+
+        live_a = (live_a && !effect.writes_a) || effect.reads_a;
+        live_d = (live_d && !effect.writes_d) || effect.reads_d;
+    }
+
+    let mut dead = dead.into_iter();
+    lines.retain(|_| !dead.next().unwrap_or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Block;
+
+    fn run(pass: fn(&mut Lines), code: &str) -> Vec<String> {
+        let mut lines: Lines = code.lines().map(|line| (0, line.to_string())).collect();
+        pass(&mut lines);
+        lines.into_iter().map(|(_, line)| line).collect()
+    }
+
+    #[test]
+    fn cancels_a_push_followed_by_a_pop() {
+        assert_eq!(
+            run(
+                cancel_push_pop,
+                "D=M\n@SP\nM=M+1\nA=M-1\nM=D\n@SP\nAM=M-1\nD=M\n@R13"
+            ),
+            ["D=M", "@SP", "A=M", "@R13"]
+        );
+    }
+
+    #[test]
+    fn stores_a_push_followed_by_a_segment_pop_directly() {
+        let pop = "@2\nD=A\n@THAT\nD=D+M\n@R13\nM=D\n@SP\nAM=M-1\nD=M\n@R13\nA=M\nM=D";
+
+        assert_eq!(
+            run(
+                cancel_push_pop,
+                &format!("D=M\n@SP\nM=M+1\nA=M-1\nM=D\n{pop}")
+            ),
+            [
+                "D=M", "@R13", "M=D", "@THAT", "D=M", "@2", "D=D+A", "@R13", "M=D+M", "D=M-D",
+                "A=M-D", "M=D"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_a_push_and_pop_around_a_label() {
+        let code = "@SP\nM=M+1\nA=M-1\nM=D\n(L)\n@SP\nAM=M-1\nD=M";
+        assert_eq!(run(cancel_push_pop, code), code.lines().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keeps_a_push_and_pop_around_a_jump() {
+        let code = "@SP\nM=M+1\nA=M-1\nM=D\n@L\nD;JGT\n@SP\nAM=M-1\nD=M";
+        assert_eq!(run(cancel_push_pop, code), code.lines().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drops_loads_of_what_a_already_holds() {
+        assert_eq!(
+            run(drop_known_loads, "@R13\nM=D\n@R13\nD=M"),
+            ["@R13", "M=D", "D=M"]
+        );
+        assert_eq!(
+            run(drop_known_loads, "@SP\nA=M-1\nM=D\n@SP\nA=M-1\nD=M"),
+            ["@SP", "A=M-1", "M=D", "D=M"]
+        );
+    }
+
+    #[test]
+    fn reloads_after_a_label_or_a_write_to_a() {
+        let code = "@R13\nM=D\n(L)\n@R13\nD=M\nA=D\n@R13\nM=0";
+        assert_eq!(
+            run(drop_known_loads, code),
+            code.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn drops_writes_that_are_never_read() {
+        assert_eq!(
+            run(drop_dead_writes, "D=A\nD=M\n@R13\nM=D"),
+            ["D=M", "@R13", "M=D"]
+        );
+        assert_eq!(run(drop_dead_writes, "@R13\n@R14\nM=0"), ["@R14", "M=0"]);
+    }
+
+    #[test]
+    fn keeps_writes_that_are_read_later() {
+        let code = "D=A\n@R13\nM=D";
+        assert_eq!(
+            run(drop_dead_writes, code),
+            code.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn keeps_writes_before_a_label_or_a_jump() {
+        let code = "D=A\n(L)\nD=M\n@R13\nM=D";
+        assert_eq!(
+            run(drop_dead_writes, code),
+            code.lines().collect::<Vec<_>>()
+        );
+
+        let code = "D=A\n@R13\nM;JGT\nD=0";
+        assert_eq!(
+            run(drop_dead_writes, code),
+            code.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn keeps_the_rewritten_lines_in_their_blocks() {
+        let block = |code: &str| Block {
+            comment: String::new(),
+            code: code.lines().map(str::to_string).collect(),
+            location: None,
+        };
+        let mut output = Output {
+            blocks: vec![
+                block("@SP\nM=M+1\nA=M-1\nM=D"),
+                block("@SP\nAM=M-1\nD=M\n@R13\nM=D"),
+            ],
+        };
+
+        optimize(&mut output);
+
+        // `@SP A=M` from the cancelled pair is dead once `@R13` follows
+        assert!(output.blocks[0].code.is_empty());
+        assert_eq!(output.blocks[1].code, ["@R13", "M=D"]);
+    }
+}
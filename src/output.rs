@@ -0,0 +1,168 @@
+use std::fmt::{self, Write};
+
+use crate::module::{functions, Module};
+
+// what the comment in front of the code of each command says
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Comments {
+    None,
+    // the command as written in the source
+    #[default]
+    Command,
+    // the command, where it comes from and its ROM address
+    Verbose,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub module: usize,
+    pub instruction: usize,
+}
+
+pub struct Block {
+    pub comment: String,
+    pub code: Vec<String>,
+    // none for code that doesn't come from a VM instruction, like the bootstrap
+    pub location: Option<Location>,
+}
+
+impl Block {
+    pub fn size(&self) -> usize {
+        self.code.iter().filter(|line| !is_label(line)).count()
+    }
+}
+
+pub fn is_label(line: &str) -> bool {
+    line.starts_with('(')
+}
+
+pub struct Output {
+    pub blocks: Vec<Block>,
+}
+
+impl Output {
+    pub fn lines(&self) -> impl Iterator<Item = &str> + Clone {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.code.iter().map(String::as_str))
+    }
+
+    // the ROM address of the first instruction of each block
+    pub fn addresses(&self) -> Vec<usize> {
+        self.blocks
+            .iter()
+            .scan(0, |address, block| {
+                let start = *address;
+                *address += block.size();
+
+                Some(start)
+            })
+            .collect()
+    }
+
+    // the first line of the code of each block in the written assembly, 1-based
+    pub fn line_numbers(&self, comments: Comments) -> Vec<usize> {
+        let comment_lines = (comments != Comments::None) as usize;
+
+        self.blocks
+            .iter()
+            .scan(1, |line, block| {
+                let start = *line + comment_lines;
+                *line = start + block.code.len();
+
+                Some(start)
+            })
+            .collect()
+    }
+
+    pub fn size(&self) -> usize {
+        self.blocks.iter().map(Block::size).sum()
+    }
+
+    // the assembly of the modules the output was translated from
+    pub fn render(&self, modules: &[Module], comments: Comments) -> String {
+        let functions = functions(modules);
+        let mut result = String::new();
+
+        for (block, address) in self.blocks.iter().zip(self.addresses()) {
+            match comments {
+                Comments::None => {}
+                Comments::Command => {
+                    let _ = writeln!(result, "// {0}", block.comment);
+                }
+                Comments::Verbose => {
+                    let mut details = vec![];
+
+                    if let Some(location) = block.location {
+                        let module = &modules[location.module];
+                        let line = module.instructions[location.instruction].span.line;
+
+                        details.push(format!("{0}:{line}", module.file_name));
+
+                        if let Some(function) = functions[location.module][location.instruction] {
+                            details.push(format!("in {function}"));
+                        }
+                    }
+
+                    details.push(format!("rom {address}"));
+
+                    let _ = writeln!(result, "// {0} ({1})", block.comment, details.join(", "));
+                }
+            }
+
+            for line in &block.code {
+                let _ = writeln!(result, "{line}");
+            }
+        }
+
+        result
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the command comments don't need the modules
+        f.write_str(&self.render(&[], Comments::Command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, translate_modules, Options};
+
+    #[test]
+    fn renders_each_comments_mode() {
+        let sources = [("Main.vm", "function Main.f 0\npush constant 7\n")];
+        let modules = parse(&sources, &Options::default()).unwrap();
+        let output = translate_modules(&modules, &Options::default());
+
+        let plain = output.render(&modules, Comments::None);
+        assert!(!plain.contains("//"));
+        assert_eq!(plain.lines().count(), output.lines().count());
+
+        let command = output.render(&modules, Comments::Command);
+        assert!(command.contains("// push constant 7\n@7\n"));
+        assert_eq!(command, output.to_string());
+
+        let verbose = output.render(&modules, Comments::Verbose);
+        assert!(verbose.starts_with("// function Main.f 0 (Main.vm:1, in Main.f, rom 0)\n"));
+        assert!(verbose.contains("// push constant 7 (Main.vm:2, in Main.f, rom 0)\n"));
+    }
+
+    #[test]
+    fn labels_take_no_rom() {
+        let block = Block {
+            comment: "label LOOP".to_string(),
+            code: vec!["(Main.f$LOOP)".to_string(), "@SP".to_string()],
+            location: None,
+        };
+        let output = Output {
+            blocks: vec![block],
+        };
+
+        assert_eq!(output.size(), 1);
+        assert_eq!(output.line_numbers(Comments::None), [1]);
+        assert_eq!(output.line_numbers(Comments::Command), [2]);
+    }
+}
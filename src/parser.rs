@@ -1,93 +1,524 @@
-use anyhow::Result;
-use regex::Regex;
-use std::{fs, path::Path};
+use std::path::Path;
 
-use crate::commands::{Command, CommandType};
+use crate::{
+    commands::{arithmetic::ArithmeticType, memory_access::MemorySegment},
+    diagnostic::Diagnostic,
+    instruction::{Instruction, Span, VmInstruction},
+    lexer::{Lexer, Line, Token},
+    module::Module,
+};
 
 pub struct Parser {
-    pub file: String,
+    pub path: String,
     pub file_name: String,
-    pub nth: usize,
-    pub function_name: Option<String>,
-    pub ret: u16,
+    pub source: String,
+    in_function: bool,
+    lenient: bool,
+    extended_constants: bool,
 }
 
 impl Parser {
-    pub fn new(file_path: &Path) -> Result<Self> {
-        let file = fs::read_to_string(file_path)?;
-
-        Ok(Self {
-            file,
-            file_name: file_path
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .into_owned(),
-            function_name: None,
-            ret: 0,
-            nth: 0,
-        })
+    // `path` names the source in diagnostics, its file stem names the statics
+    pub fn new(path: &str, source: &str) -> Self {
+        let file_name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string());
+
+        Self {
+            path: path.to_string(),
+            file_name,
+            source: source.to_string(),
+            in_function: false,
+            lenient: false,
+            extended_constants: false,
+        }
     }
 
-    fn clean(command: &str) -> &str {
-        command.split("//").nth(0).unwrap().trim()
+    // skips the temp bound and `pop constant` checks
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
     }
 
-    pub fn has_more_command(&mut self) -> bool {
-        let mut iterator = self.file.lines().skip(self.nth).enumerate();
-        let mut counter;
+    // accepts constants from -32768 to 65535, stored as their 16-bit word
+    pub fn extended_constants(mut self, extended_constants: bool) -> Self {
+        self.extended_constants = extended_constants;
+        self
+    }
 
-        while let Some((n, c)) = iterator.next() {
-            let c = c.trim();
-            counter = n + 1;
+    pub fn parse(mut self) -> Result<Module, Vec<Diagnostic>> {
+        let mut instructions = vec![];
+        let mut diagnostics = vec![];
+        let source = std::mem::take(&mut self.source);
 
-            if c.starts_with("//") || c.len() <= 0 {
-                continue;
+        for line in Lexer::new(&source) {
+            match self.parse_line(&line) {
+                Ok(kind) => instructions.push(Instruction {
+                    kind,
+                    span: Self::span(&line, line.tokens[0], line.tokens[line.tokens.len() - 1]),
+                }),
+                Err(diagnostic) => diagnostics.push(diagnostic),
             }
+        }
 
-            let function_pattern =
-                Regex::new(r"function\s+([\w\.]+)\s+(\d+)").expect("to be regex");
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
 
-            if let Some(pat) = function_pattern.captures(c) {
-                self.function_name =
-                    Some(pat.get(1).expect("a function name").as_str().to_string());
-                self.ret = 0;
+        Ok(Module::new(self.path, self.file_name, source, instructions))
+    }
+
+    fn span(line: &Line, first: Token, last: Token) -> Span {
+        Span {
+            line: line.number,
+            column: first.column,
+            length: last.column + last.text.chars().count() - first.column,
+        }
+    }
+
+    fn error(&self, line: &Line, first: Token, last: Token, message: String) -> Diagnostic {
+        let span = Self::span(line, first, last);
+
+        Diagnostic::new(message).at(&self.path, span.line, span.column, span.length, line.text)
+    }
+
+    fn parse_line(&mut self, line: &Line) -> Result<VmInstruction, Diagnostic> {
+        let keyword = line.tokens[0];
+
+        if let Some(command_type) = ArithmeticType::parse(keyword.text) {
+            self.expect_operands::<0>(line, keyword.text)?;
+
+            return Ok(VmInstruction::Arithmetic(command_type));
+        }
+
+        match keyword.text {
+            "push" | "pop" => {
+                let usage = format!("{0} <segment> <index>", keyword.text);
+                let [segment, index] = self.expect_operands(line, &usage)?;
+
+                let segment_type = MemorySegment::parse(segment.text).ok_or_else(|| {
+                    self.error(
+                        line,
+                        segment,
+                        segment,
+                        format!("unknown segment `{0}`", segment.text),
+                    )
+                    .with_hint(
+                        "expected one of argument, local, static, constant, this, that, \
+                         pointer or temp",
+                    )
+                })?;
+
+                let i = if segment_type == MemorySegment::Constant {
+                    self.parse_constant(line, index)?
+                } else {
+                    self.parse_number(line, index)?
+                };
+
+                if segment_type == MemorySegment::Pointer && i > 1 {
+                    return Err(self
+                        .error(line, index, index, format!("invalid pointer index `{i}`"))
+                        .with_hint(
+                            "the pointer segment only has the entries 0 (this) and 1 (that)",
+                        ));
+                }
+
+                if segment_type == MemorySegment::Temp && i > 7 && !self.lenient {
+                    return Err(self
+                        .error(line, index, index, format!("invalid temp index `{i}`"))
+                        .with_note(format!(
+                            "temp {i} would be RAM[{0}], past the end of the temp segment",
+                            5 + i
+                        ))
+                        .with_hint(
+                            "the temp segment only has the entries 0 to 7 (RAM[5] to RAM[12])",
+                        ));
+                }
+
+                if segment_type == MemorySegment::Constant && keyword.text == "pop" && !self.lenient
+                {
+                    return Err(self
+                        .error(
+                            line,
+                            keyword,
+                            index,
+                            "cannot pop into the constant segment".to_string(),
+                        )
+                        .with_hint("use `pop temp 0` to discard the top of the stack"));
+                }
+
+                Ok(if keyword.text == "push" {
+                    VmInstruction::Push(segment_type, i)
+                } else {
+                    VmInstruction::Pop(segment_type, i)
+                })
             }
+            "label" | "goto" | "if-goto" => {
+                let usage = format!("{0} <label>", keyword.text);
+                let [label] = self.expect_operands(line, &usage)?;
+                let label = self.identifier(line, label, "label")?;
 
-            let call_pattern = Regex::new(r"call\s+([\w\.]+)\s+(\d+)").expect("to be regex");
+                Ok(match keyword.text {
+                    "label" => VmInstruction::Label(label),
+                    "goto" => VmInstruction::Goto(label),
+                    _ => VmInstruction::IfGoto(label),
+                })
+            }
+            "function" | "call" => {
+                let usage = if keyword.text == "function" {
+                    "function <name> <nLocals>"
+                } else {
+                    "call <name> <nArgs>"
+                };
+                let [name, n] = self.expect_operands(line, usage)?;
 
-            if let Some(_) = call_pattern.captures(c) {
-                self.ret += 1;
+                let name = self.identifier(line, name, "function name")?;
+                let n = self.parse_number(line, n)?;
+
+                Ok(if keyword.text == "function" {
+                    self.in_function = true;
+                    VmInstruction::Function(name, n)
+                } else {
+                    VmInstruction::Call(name, n)
+                })
+            }
+            "return" => {
+                self.expect_operands::<0>(line, "return")?;
+
+                if !self.in_function {
+                    return Err(self.outside_function(line, keyword));
+                }
+
+                Ok(VmInstruction::Return)
             }
+            _ => Err(self
+                .error(
+                    line,
+                    keyword,
+                    keyword,
+                    format!("unknown command `{0}`", keyword.text),
+                )
+                .with_hint(
+                    "expected one of add, sub, neg, eq, gt, lt, and, or, not, push, pop, \
+                     label, goto, if-goto, function, call or return",
+                )),
+        }
+    }
 
-            self.nth = self.nth + counter;
+    fn expect_operands<'a, const N: usize>(
+        &self,
+        line: &Line<'a>,
+        usage: &str,
+    ) -> Result<[Token<'a>; N], Diagnostic> {
+        let keyword = line.tokens[0];
+        let operands = &line.tokens[1..];
 
-            return true;
+        if operands.len() > N {
+            return Err(self
+                .error(
+                    line,
+                    operands[N],
+                    operands[operands.len() - 1],
+                    format!("unexpected operand `{0}`", operands[N].text),
+                )
+                .with_hint(format!("expected `{usage}`")));
         }
 
-        return false;
+        operands.try_into().map_err(|_| {
+            self.error(
+                line,
+                keyword,
+                line.tokens[line.tokens.len() - 1],
+                format!("missing operands for `{0}`", keyword.text),
+            )
+            .with_hint(format!("expected `{usage}`"))
+        })
     }
 
-    pub fn advance<'a>(&'a self) -> (String, Box<dyn Command + 'a>) {
-        let command: &str = Self::clean(
-            self.file
-                .lines()
-                .nth(self.nth - 1)
-                .expect(&format!("a command [{0}]", self.nth)),
+    // letters, digits, `_`, `.` and `:`, not starting with a digit
+    fn identifier(&self, line: &Line, token: Token, kind: &str) -> Result<String, Diagnostic> {
+        let invalid = token
+            .text
+            .chars()
+            .find(|&c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | ':')));
+        let hint = format!(
+            "{kind}s may only contain letters, digits, `_`, `.` and `:`, and may not start with \
+             a digit"
         );
 
-        return (
-            command.to_string(),
-            CommandType::new(
-                command,
-                &self.file_name,
-                match &self.function_name {
-                    Some(name) => Some(name),
-                    None => None,
-                },
-                self.ret,
+        if let Some(c) = invalid {
+            let mut diagnostic = self
+                .error(
+                    line,
+                    token,
+                    token,
+                    format!("invalid {kind} `{0}`", token.text),
+                )
+                .with_note(format!("`{c}` is not allowed in a {kind}"));
+
+            if c == '$' {
+                diagnostic = diagnostic.with_note(
+                    "`$` separates the function from the label in the generated symbols",
+                );
+            }
+
+            return Err(diagnostic.with_hint(hint));
+        }
+
+        if token.text.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(self
+                .error(
+                    line,
+                    token,
+                    token,
+                    format!("invalid {kind} `{0}`", token.text),
+                )
+                .with_note(format!("a {kind} may not start with a digit"))
+                .with_hint(hint));
+        }
+
+        Ok(token.text.to_string())
+    }
+
+    fn parse_number(&self, line: &Line, token: Token) -> Result<u16, Diagnostic> {
+        if !token.text.chars().all(|c| c.is_ascii_digit()) {
+            return Err(self
+                .error(
+                    line,
+                    token,
+                    token,
+                    format!("invalid number `{0}`", token.text),
+                )
+                .with_hint("expected a non-negative decimal integer"));
+        }
+
+        token
+            .text
+            .parse::<u16>()
+            .ok()
+            .filter(|&n| n <= i16::MAX as u16)
+            .ok_or_else(|| {
+                self.error(
+                    line,
+                    token,
+                    token,
+                    format!("number `{0}` is too large", token.text),
+                )
+                .with_hint("expected a value between 0 and 32767")
+            })
+    }
+
+    fn parse_constant(&self, line: &Line, token: Token) -> Result<u16, Diagnostic> {
+        let digits = token.text.strip_prefix('-').unwrap_or(token.text);
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return self.parse_number(line, token);
+        }
+
+        let error = |message: String| self.error(line, token, token, message);
+        // anything too long for an i64 is out of range either way
+        let value = token
+            .text
+            .parse::<i64>()
+            .unwrap_or(if digits == token.text {
+                i64::MAX
+            } else {
+                i64::MIN
+            });
+
+        if self.extended_constants {
+            return u16::try_from(value)
+                .ok()
+                .or_else(|| i16::try_from(value).ok().map(|value| value as u16))
+                .ok_or_else(|| {
+                    error(format!("constant `{0}` is out of range", token.text))
+                        .with_note("a constant has to fit in 16 bits")
+                        .with_hint("expected a value between -32768 and 65535")
+                });
+        }
+
+        if value < 0 {
+            return Err(error(format!("negative constant `{0}`", token.text))
+                .with_note("the constants of the VM go from 0 to 32767")
+                .with_hint(format!(
+                    "push `{digits}` and negate it with `neg`, or translate with \
+                     `--extended-constants`"
+                )));
+        }
+
+        if value > i16::MAX as i64 {
+            return Err(error(format!("constant `{0}` is out of range", token.text))
+                .with_note(
+                    "the constants of the VM go from 0 to 32767, the most an A instruction loads",
+                )
+                .with_hint(
+                    "translate with `--extended-constants` to allow 16-bit values up to 65535",
+                ));
+        }
+
+        Ok(value as u16)
+    }
+
+    fn outside_function(&self, line: &Line, keyword: Token) -> Diagnostic {
+        self.error(
+            line,
+            keyword,
+            line.tokens[line.tokens.len() - 1],
+            format!("`{0}` outside of a function", keyword.text),
+        )
+        .with_hint("declare the enclosing function with `function <name> <nLocals>`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Vec<VmInstruction>, Vec<Diagnostic>> {
+        Parser::new("dir/Main.vm", source).parse().map(|module| {
+            module
+                .instructions
+                .into_iter()
+                .map(|instruction| instruction.kind)
+                .collect()
+        })
+    }
+
+    fn error(source: &str) -> String {
+        parse(source).unwrap_err().remove(0).message
+    }
+
+    #[test]
+    fn parses_every_command() {
+        assert_eq!(
+            parse(
+                "function Main.main 2\npush local 1\npop static 3\nadd\nlabel LOOP\ngoto LOOP\n\
+                 if-goto LOOP\ncall Main.f 1\nreturn\n"
             )
-            .expect(&format!("valid command type [{}]", command)),
+            .unwrap(),
+            [
+                VmInstruction::Function("Main.main".to_string(), 2),
+                VmInstruction::Push(MemorySegment::Lcl, 1),
+                VmInstruction::Pop(MemorySegment::Static, 3),
+                VmInstruction::Arithmetic(ArithmeticType::Add),
+                VmInstruction::Label("LOOP".to_string()),
+                VmInstruction::Goto("LOOP".to_string()),
+                VmInstruction::IfGoto("LOOP".to_string()),
+                VmInstruction::Call("Main.f".to_string(), 1),
+                VmInstruction::Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_cover_the_command() {
+        let module = Parser::new("Main.vm", "\n   push constant 10 // ten\n")
+            .parse()
+            .unwrap();
+        let span = module.instructions[0].span;
+
+        assert_eq!((span.line, span.column, span.length), (2, 4, 16));
+        assert_eq!(module.text(span), "push constant 10");
+    }
+
+    #[test]
+    fn reports_every_invalid_line() {
+        let errors = parse("push\nfoo\nadd 1\npush locl 0\npush local x\n").unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+
+        assert_eq!(
+            messages,
+            [
+                "missing operands for `push`",
+                "unknown command `foo`",
+                "unexpected operand `1`",
+                "unknown segment `locl`",
+                "invalid number `x`",
+            ]
+        );
+        assert_eq!(errors[1].line, 2);
+    }
+
+    #[test]
+    fn rejects_return_outside_of_a_function() {
+        assert_eq!(error("return"), "`return` outside of a function");
+    }
+
+    #[test]
+    fn checks_the_pointer_index() {
+        assert_eq!(error("push pointer 2"), "invalid pointer index `2`");
+    }
+
+    #[test]
+    fn checks_the_temp_bound_and_pop_constant() {
+        assert_eq!(error("pop temp 8"), "invalid temp index `8`");
+        assert_eq!(
+            error("pop constant 1"),
+            "cannot pop into the constant segment"
+        );
+        assert!(parse("pop temp 7\npush constant 1\n").is_ok());
+
+        let lenient = Parser::new("Main.vm", "pop temp 8\npop constant 1\n")
+            .lenient(true)
+            .parse();
+        assert!(lenient.is_ok());
+    }
+
+    #[test]
+    fn accepts_the_identifier_grammar() {
+        for label in ["WHILE_EXP.0", "IF:TRUE", "loop.1"] {
+            let source = format!("function Main.main 0\nlabel {label}\ngoto {label}\n");
+
+            assert_eq!(
+                parse(&source).unwrap()[1..],
+                [
+                    VmInstruction::Label(label.to_string()),
+                    VmInstruction::Goto(label.to_string())
+                ]
+            );
+        }
+
+        assert_eq!(error("function 1abc 0"), "invalid function name `1abc`");
+        assert_eq!(
+            error("function Main.main 0\nlabel 1abc\n"),
+            "invalid label `1abc`"
+        );
+        assert_eq!(
+            error("function Main.main 0\ngoto a$b\n"),
+            "invalid label `a$b`"
+        );
+    }
+
+    #[test]
+    fn checks_the_constant_range() {
+        assert_eq!(
+            parse("push constant 32767").unwrap(),
+            [VmInstruction::Push(MemorySegment::Constant, 32767)]
+        );
+        assert_eq!(
+            error("push constant 32768"),
+            "constant `32768` is out of range"
+        );
+        assert_eq!(error("push constant -1"), "negative constant `-1`");
+
+        let extended = |source| {
+            Parser::new("Main.vm", source)
+                .extended_constants(true)
+                .parse()
+                .map(|module| module.instructions[0].kind.clone())
+        };
+        assert_eq!(
+            extended("push constant -1").unwrap(),
+            VmInstruction::Push(MemorySegment::Constant, 0xffff)
+        );
+        assert_eq!(
+            extended("push constant 65535").unwrap(),
+            VmInstruction::Push(MemorySegment::Constant, 0xffff)
+        );
+        assert_eq!(
+            extended("push constant 65536").unwrap_err()[0].message,
+            "constant `65536` is out of range"
         );
     }
 }
@@ -0,0 +1,663 @@
+use crate::{
+    cpu::Cpu,
+    diagnostic::Diagnostic,
+    instruction::Span,
+    interpreter::{Interpreter, ARG, LCL, SP, TEMP, THAT, THIS},
+};
+
+// a nand2tetris `.tst` test script
+pub struct Script {
+    pub path: String,
+    pub source: String,
+    pub load: Option<String>,
+    pub output_file: Option<String>,
+    pub compare_to: Option<String>,
+    statements: Vec<Statement>,
+}
+
+enum Statement {
+    OutputList(Vec<Column>),
+    Set(Variable, i16),
+    Repeat(u64, Vec<Statement>),
+    TickTock,
+    VmStep,
+    Output,
+    Echo,
+}
+
+struct Variable {
+    name: String,
+    index: Option<usize>,
+    span: Span,
+}
+
+// `RAM[256]%D1.6.1`: the value, its format and its left padding, width and right padding
+struct Column {
+    variable: Variable,
+    format: char,
+    left: usize,
+    width: usize,
+    right: usize,
+}
+
+struct Token<'a> {
+    text: &'a str,
+    span: Span,
+}
+
+// the machine a script drives, the CPU for `.asm` scripts and the interpreter for `.vm` ones
+pub enum Machine<'a> {
+    Cpu(Cpu),
+    Vm(Interpreter<'a>),
+}
+
+impl<'a> Machine<'a> {
+    fn get(&self, variable: &Variable) -> Result<i16, String> {
+        let address = self.address(variable)?;
+
+        match self {
+            Machine::Cpu(cpu) => match (variable.name.as_str(), address) {
+                ("PC", _) => Ok(cpu.pc as i16),
+                ("A", _) => Ok(cpu.a),
+                ("D", _) => Ok(cpu.d),
+                (_, Some(address)) => Ok(cpu.ram[address]),
+                _ => unreachable!(),
+            },
+            Machine::Vm(vm) => vm.read(address.unwrap_or_default()),
+        }
+    }
+
+    fn set(&mut self, variable: &Variable, value: i16) -> Result<(), String> {
+        let address = self.address(variable)?;
+
+        match self {
+            Machine::Cpu(cpu) => match (variable.name.as_str(), address) {
+                ("PC", _) => cpu.pc = value as u16,
+                ("A", _) => cpu.a = value,
+                ("D", _) => cpu.d = value,
+                (_, Some(address)) => cpu.ram[address] = value,
+                _ => unreachable!(),
+            },
+            Machine::Vm(vm) => vm.write(address.unwrap_or_default(), value)?,
+        }
+
+        Ok(())
+    }
+
+    // the RAM address behind a variable, none for CPU registers
+    fn address(&self, variable: &Variable) -> Result<Option<usize>, String> {
+        let unknown = || format!("unknown variable `{0}`", variable.name);
+        let ram = |i: usize| match self {
+            Machine::Cpu(cpu) => cpu.ram[i] as u16 as usize,
+            Machine::Vm(vm) => vm.ram[i] as u16 as usize,
+        };
+
+        let address = match (self, variable.name.as_str(), variable.index) {
+            (_, "RAM", Some(i)) => i,
+            (Machine::Cpu(_), "PC" | "A" | "D", None) => return Ok(None),
+            (Machine::Vm(_), "sp", None) => SP,
+            (Machine::Vm(_), "local", None) => LCL,
+            (Machine::Vm(_), "argument", None) => ARG,
+            (Machine::Vm(_), "this", None) => THIS,
+            (Machine::Vm(_), "that", None) => THAT,
+            (Machine::Vm(_), "local", Some(i)) => ram(LCL) + i,
+            (Machine::Vm(_), "argument", Some(i)) => ram(ARG) + i,
+            (Machine::Vm(_), "this", Some(i)) => ram(THIS) + i,
+            (Machine::Vm(_), "that", Some(i)) => ram(THAT) + i,
+            (Machine::Vm(_), "pointer", Some(i)) if i < 2 => THIS + i,
+            (Machine::Vm(_), "temp", Some(i)) if i < 8 => TEMP + i,
+            _ => return Err(unknown()),
+        };
+
+        if address >= crate::interpreter::RAM_SIZE {
+            return Err(format!("address {address} is outside of RAM"));
+        }
+
+        Ok(Some(address))
+    }
+}
+
+impl Script {
+    pub fn parse(path: &str, source: &str) -> Result<Self, Diagnostic> {
+        let mut script = Self {
+            path: path.to_string(),
+            source: source.to_string(),
+            load: None,
+            output_file: None,
+            compare_to: None,
+            statements: vec![],
+        };
+        let tokens = tokenize(source)
+            .map_err(|span| script.diagnostic(span, "unterminated comment or string"))?;
+        let mut position = 0;
+
+        script.statements = script.statements(&tokens, &mut position, true)?;
+
+        Ok(script)
+    }
+
+    fn statements(
+        &mut self,
+        tokens: &[Token],
+        position: &mut usize,
+        top_level: bool,
+    ) -> Result<Vec<Statement>, Diagnostic> {
+        let mut statements = vec![];
+
+        loop {
+            let Some(keyword) = tokens.get(*position) else {
+                if top_level {
+                    return Ok(statements);
+                }
+
+                let last = &tokens[tokens.len() - 1];
+                return Err(self.diagnostic(last.span, "missing `}` after `repeat`"));
+            };
+            *position += 1;
+
+            if matches!(keyword.text, "," | ";") {
+                continue;
+            }
+
+            if keyword.text == "}" {
+                if top_level {
+                    return Err(self.diagnostic(keyword.span, "unexpected `}`"));
+                }
+
+                return Ok(statements);
+            }
+
+            if keyword.text == "repeat" {
+                let count = tokens.get(*position).filter(|t| t.text != "{");
+                let count = match count {
+                    Some(token) => {
+                        *position += 1;
+                        token.text.parse().map_err(|_| {
+                            self.diagnostic(
+                                token.span,
+                                format!("invalid repeat count `{0}`", token.text),
+                            )
+                        })?
+                    }
+                    None => {
+                        return Err(self
+                            .diagnostic(keyword.span, "missing repeat count")
+                            .with_hint("expected `repeat <n> { ... }`"))
+                    }
+                };
+
+                if tokens.get(*position).map(|t| t.text) != Some("{") {
+                    return Err(self
+                        .diagnostic(keyword.span, "missing `{` after `repeat`")
+                        .with_hint("expected `repeat <n> { ... }`"));
+                }
+
+                *position += 1;
+                let body = self.statements(tokens, position, false)?;
+                statements.push(Statement::Repeat(count, body));
+                continue;
+            }
+
+            let start = *position;
+
+            while tokens
+                .get(*position)
+                .is_some_and(|t| !matches!(t.text, "," | ";" | "{" | "}"))
+            {
+                *position += 1;
+            }
+
+            let operands = &tokens[start..*position];
+            let file = || {
+                operands.first().map(|t| t.text.to_string()).ok_or_else(|| {
+                    self.diagnostic(
+                        keyword.span,
+                        format!("missing file name after `{0}`", keyword.text),
+                    )
+                })
+            };
+
+            let statement = match keyword.text {
+                "load" => {
+                    self.load = Some(operands.first().map_or("", |t| t.text).to_string());
+                    continue;
+                }
+                "output-file" => {
+                    self.output_file = Some(file()?);
+                    continue;
+                }
+                "compare-to" => {
+                    self.compare_to = Some(file()?);
+                    continue;
+                }
+                "output-list" => Statement::OutputList(
+                    operands
+                        .iter()
+                        .map(|t| self.column(t))
+                        .collect::<Result<_, _>>()?,
+                ),
+                "set" => {
+                    let [variable, value] = operands else {
+                        return Err(self
+                            .diagnostic(keyword.span, "expected a variable and a value")
+                            .with_hint(
+                                "expected `set <variable> <value>`, e.g. `set RAM[0] 256`",
+                            ));
+                    };
+
+                    Statement::Set(
+                        self.variable(variable.text, variable.span)?,
+                        self.value(value)?,
+                    )
+                }
+                "ticktock" | "tick" | "tock" => Statement::TickTock,
+                "vmstep" => Statement::VmStep,
+                "output" => Statement::Output,
+                "echo" | "clear-echo" => Statement::Echo,
+                _ => {
+                    return Err(self
+                        .diagnostic(keyword.span, format!("unknown command `{0}`", keyword.text))
+                        .with_hint(
+                            "expected one of load, output-file, compare-to, output-list, set, \
+                             repeat, ticktock, vmstep, output or echo",
+                        ))
+                }
+            };
+
+            // `tick` and `tock` make one cycle together
+            if keyword.text != "tick" {
+                statements.push(statement);
+            }
+        }
+    }
+
+    fn variable(&self, text: &str, span: Span) -> Result<Variable, Diagnostic> {
+        let invalid = || self.diagnostic(span, format!("invalid variable `{text}`"));
+
+        let (name, index) = match text.split_once('[') {
+            Some((name, index)) => {
+                let index = index
+                    .strip_suffix(']')
+                    .and_then(|index| index.parse().ok())
+                    .ok_or_else(invalid)?;
+
+                (name, Some(index))
+            }
+            None => (text, None),
+        };
+
+        Ok(Variable {
+            name: name.to_string(),
+            index,
+            span,
+        })
+    }
+
+    // `%D-1`, `%X7FFF`, `%B101` or plain decimal
+    fn value(&self, token: &Token) -> Result<i16, Diagnostic> {
+        let text = token.text;
+        let parsed = match text.strip_prefix('%') {
+            Some(rest) => rest.chars().next().and_then(|radix| {
+                let digits = &rest[radix.len_utf8()..];
+
+                match radix {
+                    'D' => digits.parse::<i32>().ok(),
+                    'X' => i32::from_str_radix(digits, 16).ok(),
+                    'B' => i32::from_str_radix(digits, 2).ok(),
+                    _ => None,
+                }
+            }),
+            None => text.parse::<i32>().ok(),
+        };
+
+        parsed
+            .filter(|&value| (i16::MIN as i32..=u16::MAX as i32).contains(&value))
+            .map(|value| value as i16)
+            .ok_or_else(|| self.diagnostic(token.span, format!("invalid value `{text}`")))
+    }
+
+    fn column(&self, token: &Token) -> Result<Column, Diagnostic> {
+        let (name, format) = token
+            .text
+            .split_once('%')
+            .unwrap_or((token.text, "B1.16.1"));
+        let span = Span {
+            length: name.chars().count(),
+            ..token.span
+        };
+        let invalid = || {
+            self.diagnostic(token.span, format!("invalid output format `%{format}`"))
+                .with_hint(
+                    "expected `<variable>%<D|X|B><left>.<width>.<right>`, e.g. `RAM[0]%D1.6.1`",
+                )
+        };
+
+        let mut chars = format.chars();
+        let kind = chars
+            .next()
+            .filter(|c| "DXBS".contains(*c))
+            .ok_or_else(invalid)?;
+        let sizes: Vec<usize> = chars
+            .as_str()
+            .split('.')
+            .map(|n| n.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let [left, width, right] = sizes[..] else {
+            return Err(invalid());
+        };
+
+        Ok(Column {
+            variable: self.variable(name, span)?,
+            format: kind,
+            left,
+            width,
+            right,
+        })
+    }
+
+    fn diagnostic(&self, span: Span, message: impl Into<String>) -> Diagnostic {
+        let line = self.source.lines().nth(span.line - 1).unwrap_or_default();
+
+        Diagnostic::new(message).at(&self.path, span.line, span.column, span.length, line)
+    }
+
+    // runs the script and returns what it output
+    pub fn run(&self, machine: &mut Machine) -> Result<String, Diagnostic> {
+        let mut output = String::new();
+        let mut columns = &[][..];
+
+        self.execute(&self.statements, machine, &mut columns, &mut output)?;
+
+        Ok(output)
+    }
+
+    fn execute<'s>(
+        &'s self,
+        statements: &'s [Statement],
+        machine: &mut Machine,
+        columns: &mut &'s [Column],
+        output: &mut String,
+    ) -> Result<(), Diagnostic> {
+        for statement in statements {
+            match statement {
+                Statement::OutputList(list) => {
+                    *columns = list;
+
+                    for column in list.iter() {
+                        let size = column.left + column.width + column.right;
+                        let name: String = column.variable_text().chars().take(size).collect();
+                        let padding = size - name.chars().count();
+
+                        output.push_str(&format!(
+                            "|{0}{name}{1}",
+                            " ".repeat(padding / 2),
+                            " ".repeat(padding - padding / 2)
+                        ));
+                    }
+
+                    output.push_str("|\n");
+                }
+                Statement::Set(variable, value) => machine
+                    .set(variable, *value)
+                    .map_err(|message| self.diagnostic(variable.span, message))?,
+                Statement::Repeat(count, body) => {
+                    for _ in 0..*count {
+                        self.execute(body, machine, columns, output)?;
+                    }
+                }
+                Statement::TickTock => match machine {
+                    Machine::Cpu(cpu) => cpu.step(),
+                    Machine::Vm(_) => {
+                        return Err(Diagnostic::new(format!(
+                            "`{0}` uses `ticktock` but loads a VM program",
+                            self.path
+                        ))
+                        .with_hint("CPU scripts load the translated `.asm` file"))
+                    }
+                },
+                Statement::VmStep => match machine {
+                    Machine::Vm(vm) => vm.step()?,
+                    Machine::Cpu(_) => {
+                        return Err(Diagnostic::new(format!(
+                            "`{0}` uses `vmstep` but loads an assembly program",
+                            self.path
+                        ))
+                        .with_hint("VM scripts load a `.vm` file or the whole directory"))
+                    }
+                },
+                Statement::Output => {
+                    for column in columns.iter() {
+                        let value = machine
+                            .get(&column.variable)
+                            .map_err(|message| self.diagnostic(column.variable.span, message))?;
+                        let text = match column.format {
+                            'X' => format!("{0:04X}", value as u16),
+                            'B' => format!("{0:016b}", value as u16),
+                            _ => value.to_string(),
+                        };
+
+                        output.push_str(&format!(
+                            "|{0}{text:>1$}{2}",
+                            " ".repeat(column.left),
+                            column.width,
+                            " ".repeat(column.right)
+                        ));
+                    }
+
+                    output.push_str("|\n");
+                }
+                Statement::Echo => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Column {
+    fn variable_text(&self) -> String {
+        match self.variable.index {
+            Some(i) => format!("{0}[{i}]", self.variable.name),
+            None => self.variable.name.clone(),
+        }
+    }
+}
+
+// words, strings, `,`, `;`, `{` and `}`, without `//` and `/* */` comments;
+// fails with the start of an unterminated comment or string
+fn tokenize(source: &str) -> Result<Vec<Token<'_>>, Span> {
+    let mut tokens = vec![];
+    let mut in_comment: Option<Span> = None;
+
+    for (n, line) in source.lines().enumerate() {
+        let mut chars = line.char_indices().peekable();
+        let mut column = 0;
+
+        while let Some((i, c)) = chars.next() {
+            column += 1;
+            let here = Span {
+                line: n + 1,
+                column,
+                length: 1,
+            };
+
+            if in_comment.is_some() {
+                if c == '*' && chars.peek().is_some_and(|&(_, c)| c == '/') {
+                    chars.next();
+                    column += 1;
+                    in_comment = None;
+                }
+
+                continue;
+            }
+
+            match c {
+                '/' if chars.peek().is_some_and(|&(_, c)| c == '/') => break,
+                '/' if chars.peek().is_some_and(|&(_, c)| c == '*') => {
+                    in_comment = Some(Span { length: 2, ..here });
+                    chars.next();
+                    column += 1;
+                }
+                ',' | ';' | '{' | '}' => tokens.push(Token {
+                    text: &line[i..i + 1],
+                    span: here,
+                }),
+                '"' => {
+                    let Some(end) = line[i + 1..].find('"') else {
+                        return Err(here);
+                    };
+                    let text = &line[i..i + end + 2];
+
+                    tokens.push(Token {
+                        text,
+                        span: Span {
+                            length: text.chars().count(),
+                            ..here
+                        },
+                    });
+
+                    for _ in 0..text.chars().count() - 1 {
+                        chars.next();
+                        column += 1;
+                    }
+                }
+                c if c.is_whitespace() => {}
+                _ => {
+                    let mut end = i + c.len_utf8();
+                    let start_column = column;
+
+                    while let Some(&(j, c)) = chars.peek() {
+                        if c.is_whitespace() || ",;{}".contains(c) {
+                            break;
+                        }
+
+                        end = j + c.len_utf8();
+                        chars.next();
+                        column += 1;
+                    }
+
+                    let text = &line[i..end];
+
+                    tokens.push(Token {
+                        text,
+                        span: Span {
+                            line: n + 1,
+                            column: start_column,
+                            length: text.chars().count(),
+                        },
+                    });
+                }
+            }
+        }
+    }
+
+    match in_comment {
+        Some(span) => Err(span),
+        None => Ok(tokens),
+    }
+}
+
+// compares an output table with the expected one cell by cell, `*` matches anything
+pub fn compare(output: &str, expected: &str) -> Result<(), Diagnostic> {
+    let cells = |line: &str| -> Vec<String> {
+        line.trim()
+            .trim_matches('|')
+            .split('|')
+            .map(|cell| cell.trim().to_string())
+            .collect()
+    };
+    let output: Vec<&str> = output.lines().collect();
+    let expected: Vec<&str> = expected
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let header = expected.first().map(|line| cells(line)).unwrap_or_default();
+
+    for (n, expected_line) in expected.iter().enumerate() {
+        let Some(output_line) = output.get(n) else {
+            return Err(Diagnostic::new(format!(
+                "the output ends before line {0} of the comparison file",
+                n + 1
+            )));
+        };
+        let expected_cells = cells(expected_line);
+        let output_cells = cells(output_line);
+
+        for (i, expected_cell) in expected_cells.iter().enumerate() {
+            let output_cell = output_cells.get(i).map_or("", String::as_str);
+
+            if expected_cell.chars().all(|c| c == '*') || expected_cell == output_cell {
+                continue;
+            }
+
+            let name = header.get(i).map_or("?", String::as_str);
+
+            return Err(Diagnostic::new(format!(
+                "the output differs from the comparison file at line {0}",
+                n + 1
+            ))
+            .with_note(format!(
+                "{name}: expected {expected_cell}, got {output_cell}"
+            ))
+            .with_note(format!("expected: {expected_line}"))
+            .with_note(format!("output:   {output_line}")));
+        }
+
+        if output_cells.len() > expected_cells.len() {
+            return Err(Diagnostic::new(format!(
+                "the output has more columns than the comparison file at line {0}",
+                n + 1
+            )));
+        }
+    }
+
+    if output.len() > expected.len() {
+        return Err(Diagnostic::new(format!(
+            "the output has {0} lines, the comparison file only {1}",
+            output.len(),
+            expected.len()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str) -> String {
+        let script = Script::parse("Test.tst", source).unwrap();
+        let mut machine = Machine::Cpu(Cpu::new(vec![]));
+
+        script.run(&mut machine).unwrap()
+    }
+
+    #[test]
+    fn reads_values_in_every_radix() {
+        let output = run("output-list RAM[0]%D1.6.1 RAM[1]%D1.6.1 RAM[2]%D1.6.1;\n\
+                          set RAM[0] %B101, set RAM[1] %X7FFF, set RAM[2] %D-1, output;");
+
+        assert_eq!(output.lines().nth(1), Some("|      5 |  32767 |     -1 |"));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for value in ["%é5", "%Q1", "%X", "%", "12a"] {
+            let error = Script::parse("Test.tst", &format!("set RAM[0] {value};"))
+                .err()
+                .unwrap();
+
+            assert_eq!(error.message, format!("invalid value `{value}`"));
+        }
+    }
+
+    #[test]
+    fn compares_with_wildcards() {
+        let expected = "| RAM[0] | RAM[1] |\n|     5 |  **** |\n";
+
+        assert!(compare("| RAM[0] | RAM[1] |\n|     5 |    12 |\n", expected).is_ok());
+        assert!(compare("| RAM[0] | RAM[1] |\n|     6 |    12 |\n", expected).is_err());
+        assert!(compare("| RAM[0] | RAM[1] |\n", expected).is_err());
+    }
+}
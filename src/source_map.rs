@@ -0,0 +1,170 @@
+use std::fmt::Write;
+
+use crate::{
+    module::{functions, json_string, Module},
+    output::{Comments, Output},
+};
+
+// where the code of a block comes from, ranges are end-exclusive
+pub struct Entry {
+    pub rom: (usize, usize),
+    // none for binary output
+    pub asm_lines: Option<(usize, usize)>,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub function: Option<String>,
+    pub instruction: String,
+}
+
+// `asm` has the comments of the written assembly, none for binary output
+pub fn build(modules: &[Module], output: &Output, asm: Option<Comments>) -> Vec<Entry> {
+    let functions = functions(modules);
+    let line_numbers = output.line_numbers(asm.unwrap_or_default());
+
+    output
+        .blocks
+        .iter()
+        .zip(output.addresses())
+        .zip(line_numbers)
+        .map(|((block, address), asm_line)| {
+            let location = block.location.map(|location| {
+                let module = &modules[location.module];
+                let instruction = &module.instructions[location.instruction];
+
+                (
+                    module.path.clone(),
+                    instruction.span.line,
+                    functions[location.module][location.instruction].map(str::to_string),
+                )
+            });
+            let (file, line, function) = match location {
+                Some((file, line, function)) => (Some(file), Some(line), function),
+                None => (None, None, None),
+            };
+
+            Entry {
+                rom: (address, address + block.size()),
+                asm_lines: asm.map(|_| (asm_line, asm_line + block.code.len())),
+                file,
+                line,
+                function,
+                instruction: block.comment.clone(),
+            }
+        })
+        .collect()
+}
+
+// tab separated `rom asm file:line function instruction`, `-` for what is unknown; the
+// instruction is copied from the source, so its whitespace is collapsed to single spaces
+pub fn text(entries: &[Entry]) -> String {
+    let mut result = String::from("# rom\tasm\tsource\tfunction\tinstruction\n");
+
+    for entry in entries {
+        let asm_lines = entry
+            .asm_lines
+            .map_or("-".to_string(), |(start, end)| format!("{start}..{end}"));
+        let source = match (&entry.file, entry.line) {
+            (Some(file), Some(line)) => format!("{file}:{line}"),
+            _ => "-".to_string(),
+        };
+        let instruction = entry.instruction.split_whitespace().collect::<Vec<_>>();
+
+        let _ = writeln!(
+            result,
+            "{0}..{1}\t{asm_lines}\t{source}\t{2}\t{3}",
+            entry.rom.0,
+            entry.rom.1,
+            entry.function.as_deref().unwrap_or("-"),
+            instruction.join(" ")
+        );
+    }
+
+    result
+}
+
+pub fn json(entries: &[Entry]) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{{\"rom_start\": {0}, \"rom_end\": {1}, \"asm_start\": {2}, \"asm_end\": {3}, \
+                 \"file\": {4}, \"line\": {5}, \"function\": {6}, \"instruction\": {7}}}",
+                entry.rom.0,
+                entry.rom.1,
+                optional(entry.asm_lines.map(|(start, _)| start.to_string())),
+                optional(entry.asm_lines.map(|(_, end)| end.to_string())),
+                optional(entry.file.as_deref().map(json_string)),
+                optional(entry.line.map(|line| line.to_string())),
+                optional(entry.function.as_deref().map(json_string)),
+                json_string(&entry.instruction)
+            )
+        })
+        .collect();
+
+    format!("[\n  {0}\n]\n", entries.join(",\n  "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, translate_modules, Options};
+
+    const SOURCE: &str = "push constant 1\nfunction Main.f 0\npush constant 2\nreturn\n";
+
+    #[test]
+    fn points_into_the_written_assembly() {
+        let modules = parse(&[("Main.vm", SOURCE)], &Options::default()).unwrap();
+        let output = translate_modules(&modules, &Options::default());
+
+        for comments in [Comments::None, Comments::Command, Comments::Verbose] {
+            let asm = output.render(&modules, comments);
+            let lines: Vec<&str> = asm.lines().collect();
+
+            for (entry, block) in build(&modules, &output, Some(comments))
+                .iter()
+                .zip(&output.blocks)
+            {
+                let (start, end) = entry.asm_lines.unwrap();
+                assert_eq!(lines[start - 1..end - 1], block.code);
+            }
+        }
+    }
+
+    #[test]
+    fn locates_every_command() {
+        let modules = parse(&[("Main.vm", SOURCE)], &Options::default()).unwrap();
+        let output = translate_modules(&modules, &Options::default());
+        let entries = build(&modules, &output, None);
+
+        let located: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.line, entry.function.as_deref()))
+            .collect();
+        assert_eq!(
+            located,
+            [
+                (Some(1), None),
+                (Some(2), Some("Main.f")),
+                (Some(3), Some("Main.f")),
+                (Some(4), Some("Main.f"))
+            ]
+        );
+        assert!(entries.iter().all(|entry| entry.asm_lines.is_none()));
+        assert_eq!(entries.last().unwrap().rom.1, output.size());
+        assert!(text(&entries).contains("\tMain.vm:3\tMain.f\tpush constant 2\n"));
+    }
+
+    #[test]
+    fn keeps_tabs_out_of_the_instruction_column() {
+        let modules = parse(&[("Main.vm", "push\tconstant  1\n")], &Options::default()).unwrap();
+        let output = translate_modules(&modules, &Options::default());
+        let text = text(&build(&modules, &output, None));
+
+        assert!(text
+            .lines()
+            .skip(1)
+            .all(|line| line.split('\t').count() == 5));
+        assert!(text.contains("\tMain.vm:1\t-\tpush constant 1\n"));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    instruction::VmInstruction,
+    module::{functions, json_string, Module},
+    output::Output,
+};
+
+const OUTSIDE: &str = "(bootstrap and shared routines)";
+
+pub struct Row {
+    pub name: String,
+    pub vm_instructions: usize,
+    pub instructions: usize,
+}
+
+// where the instructions of a program come from, largest first
+pub struct Stats {
+    pub total: usize,
+    pub functions: Vec<Row>,
+    pub files: Vec<Row>,
+    pub opcodes: Vec<Row>,
+}
+
+impl Stats {
+    pub fn build(modules: &[Module], output: &Output) -> Self {
+        let functions = functions(modules);
+        let mut tables: [HashMap<String, Row>; 3] = Default::default();
+
+        for block in &output.blocks {
+            let names = match block.location {
+                Some(location) => {
+                    let module = &modules[location.module];
+                    let function = match functions[location.module][location.instruction] {
+                        Some(function) => function.to_string(),
+                        None => format!("{0} (top level)", module.file_name),
+                    };
+
+                    [
+                        function,
+                        module.file_name.clone(),
+                        opcode(&module.instructions[location.instruction].kind),
+                    ]
+                }
+                None => [OUTSIDE, OUTSIDE, OUTSIDE].map(str::to_string),
+            };
+
+            for (table, name) in tables.iter_mut().zip(names) {
+                let row = table.entry(name.clone()).or_insert(Row {
+                    name,
+                    vm_instructions: 0,
+                    instructions: 0,
+                });
+
+                row.vm_instructions += block.location.is_some() as usize;
+                row.instructions += block.size();
+            }
+        }
+
+        let [functions, files, opcodes] = tables.map(|table| {
+            let mut rows: Vec<Row> = table.into_values().collect();
+            rows.sort_by(|a, b| {
+                b.instructions
+                    .cmp(&a.instructions)
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+            rows
+        });
+
+        Self {
+            total: output.size(),
+            functions,
+            files,
+            opcodes,
+        }
+    }
+
+    pub fn table(&self) -> String {
+        let mut result = String::new();
+
+        for (title, rows) in [
+            ("function", &self.functions),
+            ("file", &self.files),
+            ("command", &self.opcodes),
+        ] {
+            let width = rows
+                .iter()
+                .map(|row| row.name.chars().count())
+                .chain([title.len()])
+                .max()
+                .unwrap_or_default();
+
+            let _ = writeln!(
+                result,
+                "{title:<width$}  {0:>8}  {1:>12}  {2:>6}",
+                "commands", "instructions", "share"
+            );
+
+            for row in rows.iter() {
+                let _ = writeln!(
+                    result,
+                    "{0:<width$}  {1:>8}  {2:>12}  {3:>5.1}%",
+                    row.name,
+                    row.vm_instructions,
+                    row.instructions,
+                    row.instructions as f64 * 100.0 / self.total.max(1) as f64
+                );
+            }
+
+            result.push('\n');
+        }
+
+        let _ = writeln!(result, "total: {0} instructions", self.total);
+
+        result
+    }
+
+    pub fn json(&self) -> String {
+        let rows = |rows: &[Row]| {
+            let rows: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    format!(
+                        "{{\"name\": {0}, \"commands\": {1}, \"instructions\": {2}}}",
+                        json_string(&row.name),
+                        row.vm_instructions,
+                        row.instructions
+                    )
+                })
+                .collect();
+
+            format!("[\n    {0}\n  ]", rows.join(",\n    "))
+        };
+
+        format!(
+            "{{\n  \"total\": {0},\n  \"functions\": {1},\n  \"files\": {2},\n  \"commands\": {3}\n}}\n",
+            self.total,
+            rows(&self.functions),
+            rows(&self.files),
+            rows(&self.opcodes)
+        )
+    }
+}
+
+// the command with its segment, like `push local`
+fn opcode(instruction: &VmInstruction) -> String {
+    match instruction {
+        VmInstruction::Arithmetic(command_type) => command_type.as_str().to_string(),
+        VmInstruction::Push(segment, _) => format!("push {0}", segment.as_str()),
+        VmInstruction::Pop(segment, _) => format!("pop {0}", segment.as_str()),
+        VmInstruction::Label(_) => "label".to_string(),
+        VmInstruction::Goto(_) => "goto".to_string(),
+        VmInstruction::IfGoto(_) => "if-goto".to_string(),
+        VmInstruction::Function(..) => "function".to_string(),
+        VmInstruction::Call(..) => "call".to_string(),
+        VmInstruction::Return => "return".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, translate_modules, Options};
+
+    #[test]
+    fn groups_by_command() {
+        let sources = [(
+            "Main.vm",
+            "push constant 1\npush constant 2\nadd\npop temp 0\n",
+        )];
+        let modules = parse(&sources, &Options::default()).unwrap();
+        let output = translate_modules(&modules, &Options::default());
+        let stats = Stats::build(&modules, &output);
+
+        let opcodes: Vec<_> = stats
+            .opcodes
+            .iter()
+            .map(|row| (row.name.as_str(), row.vm_instructions))
+            .collect();
+        assert!(opcodes.contains(&("push constant", 2)));
+        assert!(opcodes.contains(&("add", 1)));
+        assert!(opcodes.contains(&("pop temp", 1)));
+        assert_eq!(stats.files.len(), 1);
+        assert_eq!(stats.files[0].instructions, stats.total);
+        assert!(stats
+            .table()
+            .ends_with(&format!("total: {0} instructions\n", stats.total)));
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    assembler::{self, Program},
+    cpu::Cpu,
+    diagnostic::Diagnostic,
+    instruction::VmInstruction,
+    interpreter::{Interpreter, RAM_SIZE, SP, STACK},
+    module::Module,
+    output::Output,
+    Options,
+};
+
+// cycles the translated code of a single VM instruction may take, shared routines included
+const MAX_BLOCK_CYCLES: u64 = 100_000;
+const MAX_DIFFERENCES: usize = 5;
+// the stack area above SP holds stale values that both sides may leave differently
+const STACK_END: usize = 2048;
+const SCRATCH: std::ops::Range<usize> = 13..16;
+const STATICS: std::ops::Range<usize> = 16..256;
+
+pub struct Verification {
+    pub steps: u64,
+    pub cycles: u64,
+    pub checkpoints: u64,
+    pub halted: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    // SP and what the last instruction wrote
+    Written,
+    // the pointers, the whole stack and the statics
+    Frame,
+    // everything, at the end of the run
+    All,
+}
+
+struct Verifier<'a> {
+    vm: Interpreter<'a>,
+    cpu: Cpu,
+    program: Program,
+    // ROM address of each VM instruction in program order, followed by the end of the code
+    starts: Vec<usize>,
+    is_start: Vec<bool>,
+    // optimized code only keeps memory in step at calls and returns
+    each_instruction: bool,
+    last_checkpoint: Option<usize>,
+}
+
+// runs the program through the interpreter and through the translated and assembled
+// output side by side, checking they follow the same instructions and write the same
+// memory, and fully agree on the stack and statics after every call and return and at the end
+pub fn verify(
+    modules: &[Module],
+    output: &Output,
+    options: &Options,
+    ram_values: &[(usize, i16)],
+    max_steps: u64,
+) -> Result<Verification, Diagnostic> {
+    let program = assembler::assemble("<output>", output.lines())?;
+    let addresses = output.addresses();
+    let mut starts = vec![];
+    let mut end = 0;
+
+    for (block, &address) in output.blocks.iter().zip(&addresses) {
+        if block.location.is_some() {
+            starts.push(address);
+            end = address + block.size();
+        }
+    }
+
+    starts.push(end);
+
+    let mut is_start = vec![false; program.words.len() + 1];
+
+    for &start in &starts {
+        is_start[start] = true;
+    }
+
+    let mut vm = Interpreter::new(modules);
+    let mut cpu = Cpu::new(program.words.clone());
+
+    if starts.len() != vm.program.len() + 1 {
+        return Err(Diagnostic::new(
+            "the translated output does not match the parsed program",
+        ));
+    }
+
+    vm.written = Some(vec![]);
+    cpu.ram[SP] = STACK as i16;
+
+    // before the bootstrap, which the CPU only runs once it starts
+    for &(address, value) in ram_values {
+        vm.write(address, value).map_err(Diagnostic::new)?;
+        cpu.ram[address] = value;
+    }
+
+    if options.bootstrap {
+        vm.bootstrap()?;
+    }
+
+    let mut verifier = Verifier {
+        vm,
+        cpu,
+        program,
+        starts,
+        is_start,
+        each_instruction: !options.optimize,
+        last_checkpoint: None,
+    };
+
+    verifier.run(max_steps)
+}
+
+impl<'a> Verifier<'a> {
+    fn run(&mut self, max_steps: u64) -> Result<Verification, Diagnostic> {
+        let mut checkpoints = 0;
+
+        // whatever precedes the first instruction, like the bootstrap
+        self.run_cpu(false).map_err(Diagnostic::new)?;
+
+        if self.cpu.pc as usize != self.starts[self.vm.pc] {
+            return Err(Diagnostic::new(format!(
+                "the translated code starts at ROM {0} instead of ROM {1}",
+                self.cpu.pc, self.starts[self.vm.pc]
+            )));
+        }
+
+        let mut last = None;
+
+        while !self.vm.halted && self.vm.steps < max_steps {
+            let k = self.vm.pc;
+
+            if k >= self.vm.program.len() {
+                self.vm.step()?;
+                break;
+            }
+
+            self.vm.step()?;
+            last = Some(k);
+            self.run_cpu(self.starts[k + 1] > self.starts[k])
+                .map_err(|message| self.error(k, message))?;
+
+            let end = self.starts[self.vm.program.len()];
+            let expected = self.starts[self.vm.pc.min(self.vm.program.len())];
+            // both left the program, e.g. returning to an address set up by the caller
+            let both_ended = self.vm.pc >= self.vm.program.len() && self.cpu.pc as usize >= end;
+
+            if self.cpu.pc as usize != expected && !both_ended {
+                return Err(self
+                    .error(k, "the translated code jumps somewhere else than the VM")
+                    .with_note(format!(
+                        "the VM continues at ROM {expected}, the assembly at ROM {0}",
+                        self.cpu.pc
+                    )));
+            }
+
+            let kind = &self.vm.program[k].instruction.kind;
+
+            if matches!(kind, VmInstruction::Call(..) | VmInstruction::Return) {
+                self.check(k, Scope::Frame)?;
+                checkpoints += 1;
+            } else if self.each_instruction {
+                self.check(k, Scope::Written)?;
+            }
+        }
+
+        if let (true, Some(k)) = (self.vm.halted, last) {
+            self.check_halted(k)?;
+        }
+
+        if let Some(k) = self.vm.pc.checked_sub(1).or(self.last_checkpoint) {
+            self.check(k.min(self.vm.program.len().saturating_sub(1)), Scope::All)?;
+            checkpoints += 1;
+        }
+
+        Ok(Verification {
+            steps: self.vm.steps,
+            cycles: self.cpu.cycles,
+            checkpoints,
+            halted: self.vm.halted,
+        })
+    }
+
+    // runs until the CPU reaches the code of a VM instruction
+    fn run_cpu(&mut self, at_least_once: bool) -> Result<(), String> {
+        let mut cycles = 0;
+
+        while !self.cpu.halted {
+            let pc = self.cpu.pc as usize;
+
+            if (cycles > 0 || !at_least_once) && self.is_start.get(pc).copied().unwrap_or(false) {
+                return Ok(());
+            }
+
+            if cycles == MAX_BLOCK_CYCLES {
+                return Err(format!(
+                    "the translated code did not reach the next instruction within {MAX_BLOCK_CYCLES} cycles"
+                ));
+            }
+
+            self.cpu.step();
+            cycles += 1;
+        }
+
+        Ok(())
+    }
+
+    // the VM halting only agrees with a CPU caught in a `(LOOP) @LOOP 0;JMP` halt loop, or one
+    // that ran off the end of the ROM when the VM ran off the end of the program
+    fn check_halted(&mut self, k: usize) -> Result<(), Diagnostic> {
+        let ended = self.vm.pc >= self.vm.program.len();
+
+        if ended {
+            // whatever follows the code, like the shared halt loop
+            self.run_cpu(true).ok();
+        }
+
+        let in_rom = (self.cpu.pc as usize) < self.program.words.len();
+
+        if self.cpu.halted && (ended || in_rom) {
+            return Ok(());
+        }
+
+        Err(self
+            .error(k, "the VM halts here but the translated code keeps running")
+            .with_note(format!(
+                "the assembly is at ROM {0} instead of in a halt loop",
+                self.cpu.pc
+            )))
+    }
+
+    fn error(&self, k: usize, message: impl Into<String>) -> Diagnostic {
+        let current = &self.vm.program[k];
+        let mut diagnostic =
+            self.vm.modules[current.module].diagnostic(current.instruction.span, message);
+
+        if let Some(last) = self.last_checkpoint {
+            let last = &self.vm.program[last];
+            let module = &self.vm.modules[last.module];
+
+            diagnostic = diagnostic.with_note(format!(
+                "both agreed after `{0}` at {1}:{2}",
+                last.instruction.kind, module.path, last.instruction.span.line
+            ));
+        }
+
+        diagnostic
+    }
+
+    fn check(&mut self, k: usize, scope: Scope) -> Result<(), Diagnostic> {
+        let differences = self.differences(scope);
+
+        if let Some(written) = &mut self.vm.written {
+            written.clear();
+        }
+
+        if differences.is_empty() {
+            self.last_checkpoint = Some(k);
+            return Ok(());
+        }
+
+        let count = differences.len();
+        let mut diagnostic = self.error(
+            k,
+            format!(
+                "the translated code disagrees with the VM after `{0}`",
+                self.vm.program[k].instruction.kind
+            ),
+        );
+
+        for difference in differences.into_iter().take(MAX_DIFFERENCES) {
+            diagnostic = diagnostic.with_note(difference);
+        }
+
+        if count > MAX_DIFFERENCES {
+            diagnostic = diagnostic.with_note(format!("and {0} more", count - MAX_DIFFERENCES));
+        }
+
+        Err(diagnostic)
+    }
+
+    fn differences(&self, scope: Scope) -> Vec<String> {
+        let sp = self.vm.ram[SP] as u16 as usize;
+        let return_slots: HashSet<usize> = self
+            .vm
+            .frames
+            .iter()
+            .filter(|frame| frame.lcl >= 5)
+            .map(|frame| frame.lcl - 5)
+            .collect();
+        let skipped = |address: usize| {
+            SCRATCH.contains(&address)
+                || STATICS.contains(&address)
+                || (sp..STACK_END).contains(&address)
+        };
+
+        let mut addresses = vec![SP];
+
+        if let Some(written) = &self.vm.written {
+            addresses.extend(written.iter().copied());
+        }
+
+        if scope != Scope::Written {
+            addresses.extend(0..SCRATCH.start);
+            addresses.extend(STACK..sp.min(RAM_SIZE));
+        }
+
+        if scope == Scope::All {
+            addresses.extend(STACK..RAM_SIZE);
+        }
+
+        addresses.retain(|&address| !skipped(address));
+        addresses.sort_unstable();
+        addresses.dedup();
+
+        let mut differences = vec![];
+
+        for address in addresses {
+            let expected = self.vm.ram[address];
+            let actual = self.cpu.ram[address];
+
+            if return_slots.contains(&address) {
+                let expected = self.starts.get(expected as u16 as usize).copied();
+
+                if expected != Some(actual as u16 as usize) {
+                    differences.push(format!(
+                        "return address at RAM[{address}]: VM ROM {0}, assembly ROM {actual}",
+                        expected.map_or("?".to_string(), |e| e.to_string())
+                    ));
+                }
+            } else if expected != actual {
+                differences.push(format!("RAM[{address}]: VM {expected}, assembly {actual}"));
+            }
+        }
+
+        // statics are only compared at checkpoints, naming them costs too much per step
+        if scope == Scope::Written {
+            return differences;
+        }
+
+        let variables: HashMap<&str, u16> = self
+            .program
+            .variables
+            .iter()
+            .map(|(name, address)| (name.as_str(), *address))
+            .collect();
+        let mut statics: HashMap<String, i16> = self
+            .vm
+            .statics
+            .iter()
+            .map(|(&(module, i), &value)| (self.vm.static_name(module, i), value))
+            .collect();
+
+        for (name, _) in &self.program.variables {
+            statics.entry(name.clone()).or_insert(0);
+        }
+
+        let mut statics: Vec<(String, i16)> = statics.into_iter().collect();
+        statics.sort();
+
+        for (name, expected) in statics {
+            match variables.get(name.as_str()) {
+                Some(&address) if self.cpu.ram[address as usize] != expected => {
+                    differences.push(format!(
+                        "static {name} (RAM[{address}]): VM {expected}, assembly {0}",
+                        self.cpu.ram[address as usize]
+                    ))
+                }
+                Some(_) => {}
+                None => differences.push(format!(
+                    "static {name} is set by the VM but never used by the assembly"
+                )),
+            }
+        }
+
+        differences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, translate_modules, Options};
+
+    const SOURCE: &str = "function Sys.init 0\npush constant 3\ncall Sys.double 1\npop static 0\n\
+                          label END\ngoto END\n\
+                          function Sys.double 0\npush argument 0\npush argument 0\nadd\nreturn\n";
+
+    fn options() -> Options {
+        Options {
+            bootstrap: true,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn agrees_with_the_translation() {
+        let modules = parse(&[("Sys.vm", SOURCE)], &options()).unwrap();
+        let output = translate_modules(&modules, &options());
+        let verification = verify(&modules, &output, &options(), &[], 1000).unwrap();
+
+        assert!(verification.halted);
+        assert_eq!(verification.checkpoints, 3);
+    }
+
+    #[test]
+    fn reports_a_wrong_translation() {
+        let modules = parse(&[("Sys.vm", SOURCE)], &options()).unwrap();
+        let mut output = translate_modules(&modules, &options());
+        let add = output
+            .blocks
+            .iter_mut()
+            .find(|block| block.comment == "add")
+            .unwrap();
+
+        for line in &mut add.code {
+            if line == "D=D+M" {
+                *line = "D=D-M".to_string();
+            }
+        }
+
+        let error = verify(&modules, &output, &options(), &[], 1000)
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            error.message,
+            "the translated code disagrees with the VM after `add`"
+        );
+        assert_eq!(error.line, 10);
+    }
+
+    #[test]
+    fn reports_a_translation_that_does_not_halt() {
+        let modules = parse(&[("Sys.vm", SOURCE)], &options()).unwrap();
+        let mut output = translate_modules(&modules, &options());
+        let label = output
+            .blocks
+            .iter_mut()
+            .find(|block| block.comment == "label END")
+            .unwrap();
+
+        label.code.push("D=D".to_string());
+
+        let error = verify(&modules, &output, &options(), &[], 1000)
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            error.message,
+            "the VM halts here but the translated code keeps running"
+        );
+        assert_eq!(error.line, 6);
+    }
+
+    #[test]
+    fn set_values_apply_before_the_bootstrap() {
+        let source = "function Sys.init 0\npush this 0\npop temp 0\nlabel END\ngoto END\n";
+        let options = Options {
+            bootstrap: true,
+            ..Options::default()
+        };
+        let modules = parse(&[("Sys.vm", source)], &options).unwrap();
+        let output = translate_modules(&modules, &options);
+        let verification = verify(&modules, &output, &options, &[(3, 3000)], 1000).unwrap();
+
+        assert!(verification.halted);
+    }
+}